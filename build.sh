@@ -5,3 +5,4 @@ RUSTFLAGS='-C link-arg=-s' cargo +stable build --all --target wasm32-unknown-unk
 
 cp target/wasm32-unknown-unknown/release/cheddar_coin.wasm ./res
 cp target/wasm32-unknown-unknown/release/p1_staking_pool_dyn.wasm ./res
+cp target/wasm32-unknown-unknown/release/ve_cheddar.wasm ./res
@@ -0,0 +1,306 @@
+//! Merkle root based airdrop campaigns.
+//! A campaign commits to a list of `(account_id, amount)` leaves with a merkle root.
+//! Users claim their tokens by providing a merkle proof. Unclaimed budget can be reclaimed
+//! by the campaign creator after the deadline.
+//!
+//! Leaf hash: `sha256("<account_id>:<amount>")`, where amount is a decimal string.
+//! Parent hash: `sha256(min(a, b) ++ max(a, b))` (pairs are sorted, so proofs don't need
+//! to encode the node positions).
+
+use std::convert::TryInto;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base58CryptoHash, ValidAccountId};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance, CryptoHash};
+
+use crate::events::{AirdropEvent, FtMint, FtTransfer};
+use crate::util::*;
+use crate::vesting::Timestamp;
+use crate::*;
+
+/// Source of the airdropped tokens.
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum AirdropFunding {
+    /// tokens are minted on claim. The budget is reserved from the creator minter allowance
+    /// and counted as minted by the creator when claimed.
+    Mint,
+    /// the budget is transferred from the creator to the contract pool when the campaign
+    /// is created, and claims are transferred from the pool.
+    Pool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AirdropCampaign {
+    pub creator_id: AccountId,
+    pub merkle_root: CryptoHash,
+    pub funding: AirdropFunding,
+    /// total amount of tokens which can be claimed.
+    pub budget: Balance,
+    /// amount of tokens claimed so far.
+    pub claimed: Balance,
+    /// timestamp in nanoseconds after which claims are not possible and the unclaimed
+    /// budget can be reclaimed.
+    pub deadline: Timestamp,
+    /// true if the unclaimed budget was reclaimed and the campaign is closed.
+    pub closed: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AirdropCampaignJSON {
+    pub id: u64,
+    pub creator_id: AccountId,
+    pub merkle_root: Base58CryptoHash,
+    pub funding: AirdropFunding,
+    pub budget: U128String,
+    pub claimed: U128String,
+    pub deadline: U64String,
+    pub closed: bool,
+}
+
+/// Returns the merkle leaf hash of the claim.
+pub fn airdrop_leaf(account_id: &AccountId, amount: Balance) -> CryptoHash {
+    hash(format!("{}:{}", account_id, amount).as_bytes())
+}
+
+/// Verifies the merkle proof of the `leaf` against the `root`.
+pub fn verify_merkle_proof(root: &CryptoHash, leaf: CryptoHash, proof: &[CryptoHash]) -> bool {
+    let mut h = leaf;
+    for p in proof {
+        h = if h <= *p {
+            hash(&[h, *p].concat())
+        } else {
+            hash(&[*p, h].concat())
+        };
+    }
+    h == *root
+}
+
+fn hash(data: &[u8]) -> CryptoHash {
+    env::sha256(data).try_into().unwrap()
+}
+
+impl Contract {
+    fn internal_get_airdrop(&self, campaign_id: u64) -> AirdropCampaign {
+        self.airdrops.get(&campaign_id).expect("airdrop not found")
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates a new airdrop campaign and returns its id.
+    /// Minters can create `mint` campaigns, the budget is reserved from the minter allowance.
+    /// Minters and admins can create `pool` campaigns, the budget is transferred from the
+    /// caller account to the contract.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn create_airdrop(
+        &mut self,
+        merkle_root: Base58CryptoHash,
+        budget: U128String,
+        deadline: U64String,
+        funding: AirdropFunding,
+    ) -> u64 {
+        assert_one_yocto();
+        assert!(budget.0 > 0, "airdrop budget must be positive");
+        assert!(
+            deadline.0 > env::block_timestamp(),
+            "deadline must be in the future"
+        );
+        let creator_id = env::predecessor_account_id();
+        match funding {
+            AirdropFunding::Mint => {
+                self.assert_role(Role::Minter);
+                let mut a = self.internal_get_minter_allowance(&creator_id);
+                a.reserve(budget.0);
+                self.minter_allowances.insert(&creator_id, &a);
+            }
+            AirdropFunding::Pool => {
+                assert!(
+                    self.internal_has_role(Role::Minter, &creator_id)
+                        || self.internal_has_role(Role::Admin, &creator_id),
+                    "only minters and admins can create airdrops"
+                );
+                let pool_id = env::current_account_id();
+                self.try_register_account(&pool_id, 0);
+                self.internal_transfer(&creator_id, &pool_id, budget.0, Some("airdrop".into()));
+            }
+        }
+        let id = self.next_airdrop_id;
+        self.next_airdrop_id += 1;
+        self.airdrops.insert(
+            &id,
+            &AirdropCampaign {
+                creator_id,
+                merkle_root: merkle_root.into(),
+                funding,
+                budget: budget.0,
+                claimed: 0,
+                deadline: deadline.0,
+                closed: false,
+            },
+        );
+        AirdropEvent {
+            event: "airdrop_created",
+            campaign_id: id,
+            amount: budget,
+        }
+        .emit();
+        id
+    }
+
+    /// Claims `amount` tokens from the airdrop campaign. `proof` is the merkle proof of
+    /// the `(caller, amount)` leaf. Each leaf can be claimed only once.
+    /// Claims of a `mint` campaign fail if the creator is no longer a minter or its
+    /// reservation was dropped (the minter role was revoked).
+    pub fn claim_airdrop(
+        &mut self,
+        campaign_id: u64,
+        amount: U128String,
+        proof: Vec<Base58CryptoHash>,
+    ) {
+        let account_id = env::predecessor_account_id();
+        let mut c = self.internal_get_airdrop(campaign_id);
+        assert!(
+            env::block_timestamp() < c.deadline,
+            "airdrop campaign is over"
+        );
+        let leaf = airdrop_leaf(&account_id, amount.0);
+        let proof: Vec<CryptoHash> = proof.into_iter().map(|p| p.into()).collect();
+        assert!(
+            verify_merkle_proof(&c.merkle_root, leaf, &proof),
+            "invalid merkle proof"
+        );
+        assert!(
+            self.airdrop_claims.insert(&(campaign_id, leaf)),
+            "airdrop already claimed"
+        );
+        assert!(c.claimed + amount.0 <= c.budget, "airdrop budget exceeded");
+        c.claimed += amount.0;
+        self.airdrops.insert(&campaign_id, &c);
+
+        let memo = Some("airdrop claim");
+        match c.funding {
+            AirdropFunding::Mint => {
+                // revoking the creator minter role stops its campaigns
+                assert!(
+                    self.internal_has_role(Role::Minter, &c.creator_id),
+                    "airdrop creator {} is no longer a minter",
+                    c.creator_id
+                );
+                let mut a = self.internal_get_minter_allowance(&c.creator_id);
+                a.mint_reserved(amount.0);
+                self.minter_allowances.insert(&c.creator_id, &a);
+                self.internal_record_minted(&c.creator_id, amount.0);
+                self.internal_mint(&account_id, amount.0);
+                FtMint {
+                    owner_id: &account_id,
+                    amount,
+                    memo,
+                }
+                .emit();
+            }
+            AirdropFunding::Pool => {
+                let pool_id = env::current_account_id();
+                self.try_register_account(&account_id, 0);
+                self.internal_move_balance(&pool_id, &account_id, amount.0);
+                FtTransfer {
+                    old_owner_id: &pool_id,
+                    new_owner_id: &account_id,
+                    amount,
+                    memo,
+                }
+                .emit();
+            }
+        }
+    }
+
+    /// Closes the campaign after the deadline and returns the unclaimed budget to the
+    /// creator: the reserved minter allowance is restored for `mint` campaigns and the tokens
+    /// are transferred back for `pool` campaigns. Can be called by the creator or an admin.
+    /// Revoking the minter role drops the creator reservations: if the creator is no longer
+    /// a minter (or was granted the role again), the unclaimed budget of a `mint` campaign
+    /// is not restored.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn reclaim_airdrop(&mut self, campaign_id: u64) -> U128String {
+        assert_one_yocto();
+        let mut c = self.internal_get_airdrop(campaign_id);
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == c.creator_id || self.internal_has_role(Role::Admin, &caller),
+            "only the campaign creator or an admin can reclaim the airdrop"
+        );
+        assert!(
+            env::block_timestamp() >= c.deadline,
+            "airdrop can't be reclaimed before the deadline"
+        );
+        assert!(!c.closed, "airdrop already reclaimed");
+        c.closed = true;
+        self.airdrops.insert(&campaign_id, &c);
+        let unclaimed = c.budget - c.claimed;
+        if unclaimed > 0 {
+            match c.funding {
+                AirdropFunding::Mint => {
+                    let restored = match self.minter_allowances.get(&c.creator_id) {
+                        Some(mut a) => {
+                            let restored = a.release(unclaimed);
+                            self.minter_allowances.insert(&c.creator_id, &a);
+                            restored
+                        }
+                        None => 0,
+                    };
+                    if restored < unclaimed {
+                        log!(
+                            "{} is no longer a minter, {} of the unclaimed budget is not restored",
+                            c.creator_id,
+                            unclaimed - restored
+                        );
+                    }
+                }
+                AirdropFunding::Pool => {
+                    self.internal_transfer(
+                        &env::current_account_id(),
+                        &c.creator_id,
+                        unclaimed,
+                        Some("airdrop reclaim".into()),
+                    );
+                }
+            }
+        }
+        AirdropEvent {
+            event: "airdrop_reclaimed",
+            campaign_id,
+            amount: unclaimed.into(),
+        }
+        .emit();
+        unclaimed.into()
+    }
+
+    pub fn get_airdrop(&self, campaign_id: u64) -> Option<AirdropCampaignJSON> {
+        self.airdrops
+            .get(&campaign_id)
+            .map(|c| AirdropCampaignJSON {
+                id: campaign_id,
+                creator_id: c.creator_id,
+                merkle_root: c.merkle_root.into(),
+                funding: c.funding,
+                budget: c.budget.into(),
+                claimed: c.claimed.into(),
+                deadline: c.deadline.into(),
+                closed: c.closed,
+            })
+    }
+
+    /// Returns true if the `(account_id, amount)` leaf was already claimed.
+    pub fn is_airdrop_claimed(
+        &self,
+        campaign_id: u64,
+        account_id: ValidAccountId,
+        amount: U128String,
+    ) -> bool {
+        self.airdrop_claims
+            .contains(&(campaign_id, airdrop_leaf(account_id.as_ref(), amount.0)))
+    }
+}
@@ -0,0 +1,233 @@
+//! Allowance based pull payments: an owner approves a spender to transfer up to a given
+//! amount of tokens from the owner account.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{ValidAccountId, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise, PromiseOrValue};
+
+use crate::events::FtApprove;
+use crate::util::*;
+use crate::vesting::Timestamp;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Allowance {
+    /// amount of tokens the spender can still transfer.
+    pub amount: Balance,
+    /// optional timestamp in nanoseconds after which the allowance can't be used.
+    pub expires_at: Option<Timestamp>,
+    /// NEAR deposited by the owner to cover the allowance storage.
+    /// It's refunded to the owner when the allowance is removed.
+    pub deposit: Balance,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AllowanceJSON {
+    pub amount: U128String,
+    pub expires_at: Option<U64String>,
+}
+
+impl Allowance {
+    #[inline]
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(t) => env::block_timestamp() >= t,
+            None => false,
+        }
+    }
+}
+
+impl Contract {
+    /// Removes the allowance and refunds its storage deposit to the owner.
+    fn internal_remove_allowance(&mut self, owner_id: &AccountId, spender_id: &AccountId) {
+        if let Some(a) = self
+            .allowances
+            .remove(&(owner_id.clone(), spender_id.clone()))
+        {
+            if a.deposit > 0 {
+                Promise::new(owner_id.clone()).transfer(a.deposit);
+            }
+        }
+    }
+
+    /// Decreases the allowance by `amount`.
+    /// Panics if there is no allowance, it's expired or it's not big enough.
+    fn internal_spend_allowance(
+        &mut self,
+        owner_id: &AccountId,
+        spender_id: &AccountId,
+        amount: Balance,
+    ) {
+        let key = (owner_id.clone(), spender_id.clone());
+        let mut a = self.allowances.get(&key).expect("no allowance");
+        assert!(!a.is_expired(), "allowance expired");
+        assert!(
+            amount <= a.amount,
+            "allowance exceeded, remaining allowance: {}",
+            a.amount
+        );
+        a.amount -= amount;
+        if a.amount == 0 {
+            self.internal_remove_allowance(owner_id, spender_id);
+        } else {
+            self.allowances.insert(&key, &a);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Approves `spender_id` to transfer up to `amount` tokens from the caller account,
+    /// replacing the previous allowance. `expires_at` is an optional timestamp (in nanoseconds)
+    /// after which the allowance can't be used.
+    /// Creating a new allowance requires a storage deposit (see `ft_allowance_storage_cost`),
+    /// unused deposit is refunded. Updating an existing allowance requires 1 yoctoNEAR.
+    /// Setting `amount` to 0 removes the allowance and refunds the storage deposit.
+    #[payable]
+    pub fn ft_approve(
+        &mut self,
+        spender_id: ValidAccountId,
+        amount: U128,
+        expires_at: Option<U64String>,
+    ) {
+        let owner_id = env::predecessor_account_id();
+        let spender_id: AccountId = spender_id.into();
+        assert_ne!(owner_id, spender_id, "can't approve own account");
+        if let Some(t) = expires_at {
+            assert!(
+                t.0 > env::block_timestamp(),
+                "expiration must be in the future"
+            );
+        }
+        let key = (owner_id.clone(), spender_id.clone());
+        match self.allowances.get(&key) {
+            Some(mut a) => {
+                assert_one_yocto();
+                if amount.0 == 0 {
+                    self.internal_remove_allowance(&owner_id, &spender_id);
+                } else {
+                    a.amount = amount.0;
+                    a.expires_at = expires_at.map(|t| t.0);
+                    self.allowances.insert(&key, &a);
+                }
+            }
+            None => {
+                assert!(amount.0 > 0, "allowance amount must be positive");
+                let deposit = env::attached_deposit();
+                let cost = self.allowance_storage_cost();
+                assert!(
+                    deposit >= cost,
+                    "attached deposit: {},  required: {}",
+                    deposit,
+                    cost
+                );
+                self.allowances.insert(
+                    &key,
+                    &Allowance {
+                        amount: amount.0,
+                        expires_at: expires_at.map(|t| t.0),
+                        deposit: cost,
+                    },
+                );
+                if deposit > cost {
+                    Promise::new(owner_id.clone()).transfer(deposit - cost);
+                }
+            }
+        }
+        FtApprove {
+            owner_id: &owner_id,
+            spender_id: &spender_id,
+            amount,
+            expires_at,
+        }
+        .emit();
+    }
+
+    /// Removes the allowance of `spender_id` and refunds the storage deposit.
+    #[payable]
+    pub fn ft_revoke(&mut self, spender_id: ValidAccountId) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let spender_id: AccountId = spender_id.into();
+        assert!(
+            self.allowances
+                .contains_key(&(owner_id.clone(), spender_id.clone())),
+            "no allowance"
+        );
+        self.internal_remove_allowance(&owner_id, &spender_id);
+        FtApprove {
+            owner_id: &owner_id,
+            spender_id: &spender_id,
+            amount: 0.into(),
+            expires_at: None,
+        }
+        .emit();
+    }
+
+    /// Transfers `amount` tokens from `owner_id` to `receiver_id` using the caller allowance.
+    /// Panics if the owner or the caller is frozen.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn ft_transfer_from(
+        &mut self,
+        owner_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        let spender_id = env::predecessor_account_id();
+        self.assert_not_frozen(&spender_id);
+        self.internal_spend_allowance(owner_id.as_ref(), &spender_id, amount.0);
+        self.internal_transfer(owner_id.as_ref(), receiver_id.as_ref(), amount.0, memo);
+    }
+
+    /// Like `ft_transfer_from`, but calls `ft_on_transfer` on the receiver. Unused tokens
+    /// are refunded to the `owner_id` (the allowance is not restored).
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn ft_transfer_from_call(
+        &mut self,
+        owner_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        let spender_id = env::predecessor_account_id();
+        self.assert_not_frozen(&spender_id);
+        self.internal_spend_allowance(owner_id.as_ref(), &spender_id, amount.0);
+        self.internal_transfer_call(owner_id.into(), receiver_id.into(), amount.0, memo, msg)
+            .into()
+    }
+
+    /// Returns the amount `spender_id` can transfer from `owner_id`. Returns 0 if there is
+    /// no allowance or it's expired.
+    pub fn ft_allowance(&self, owner_id: ValidAccountId, spender_id: ValidAccountId) -> U128 {
+        match self.allowances.get(&(owner_id.into(), spender_id.into())) {
+            Some(a) if !a.is_expired() => a.amount.into(),
+            _ => 0.into(),
+        }
+    }
+
+    /// Returns the allowance details, including expired allowances.
+    pub fn ft_allowance_info(
+        &self,
+        owner_id: ValidAccountId,
+        spender_id: ValidAccountId,
+    ) -> Option<AllowanceJSON> {
+        self.allowances
+            .get(&(owner_id.into(), spender_id.into()))
+            .map(|a| AllowanceJSON {
+                amount: a.amount.into(),
+                expires_at: a.expires_at.map(|t| t.into()),
+            })
+    }
+
+    /// Returns the NEAR deposit required to create a new allowance.
+    pub fn ft_allowance_storage_cost(&self) -> U128 {
+        self.allowance_storage_cost().into()
+    }
+}
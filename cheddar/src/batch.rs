@@ -0,0 +1,108 @@
+//! Batch transfers and mints (eg for airdrops).
+//! A batch is atomic: if any transfer or mint fails, the whole transaction fails and no
+//! balance is changed. One aggregated event is emitted per batch.
+
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::{assert_one_yocto, env, near_bindgen, Balance, Gas};
+
+use crate::events::{FtMint, FtTransfer};
+use crate::fee::TRANSFER_FEE_MEMO;
+use crate::util::*;
+use crate::*;
+
+/// Maximum number of entries in a single batch.
+pub const MAX_BATCH_SIZE: usize = 100;
+/// Gas required to process one batch entry (balance updates, checkpoints and votes).
+pub const GAS_PER_BATCH_ITEM: Gas = 2 * TGAS;
+/// Maximum gas which can be attached to a transaction. A full batch must fit in it, with
+/// room left for the call itself.
+pub const MAX_PREPAID_GAS: Gas = 300 * TGAS;
+
+/// Validates the batch size and checks that the transaction has enough gas to process
+/// the whole batch, so it fails early rather than running out of gas in the middle.
+fn assert_batch(len: usize) {
+    assert!(len > 0, "batch can't be empty");
+    assert!(
+        len <= MAX_BATCH_SIZE,
+        "batch too big, max batch size: {}",
+        MAX_BATCH_SIZE
+    );
+    let required = GAS_PER_BATCH_ITEM * len as Gas;
+    assert!(
+        env::prepaid_gas() - env::used_gas() >= required,
+        "not enough gas for the batch, required: {}",
+        required
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers tokens from the caller to each of the receivers. All receivers must be
+    /// registered. The transfer fee is charged for each transfer. Emits a single `ft_transfer`
+    /// event with all transfers and the total fee.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn ft_batch_transfer(
+        &mut self,
+        transfers: Vec<(ValidAccountId, U128String)>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        assert_batch(transfers.len());
+        let sender_id = env::predecessor_account_id();
+        let mut total_fee: Balance = 0;
+        let mut events: Vec<FtTransfer> = Vec::with_capacity(transfers.len() + 1);
+        for (receiver_id, amount) in transfers.iter() {
+            let (net, fee) =
+                self.internal_move_balance_with_fee(&sender_id, receiver_id.as_ref(), amount.0);
+            total_fee += fee;
+            events.push(FtTransfer {
+                old_owner_id: &sender_id,
+                new_owner_id: receiver_id.as_ref(),
+                amount: net.into(),
+                memo: memo.as_deref(),
+            });
+        }
+        if total_fee > 0 {
+            events.push(FtTransfer {
+                old_owner_id: &sender_id,
+                new_owner_id: self.fee_treasury().unwrap(),
+                amount: total_fee.into(),
+                memo: Some(TRANSFER_FEE_MEMO),
+            });
+        }
+        FtTransfer::emit_many(&events);
+    }
+
+    /// Mints tokens to each of the receivers, registering not registered receivers.
+    /// The total amount is deducted from the caller minter allowance.
+    /// Emits a single `ft_mint` event with all mints.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn ft_batch_mint(
+        &mut self,
+        mints: Vec<(ValidAccountId, U128String)>,
+        memo: Option<String>,
+    ) {
+        assert_one_yocto();
+        assert_batch(mints.len());
+        let minter_id = env::predecessor_account_id();
+        self.assert_role(Role::Minter);
+        let total: Balance = mints.iter().map(|(_, amount)| amount.0).fold(0, |acc, a| {
+            acc.checked_add(a).expect("total amount overflow")
+        });
+        self.consume_minter_allowance(&minter_id, total);
+        for (receiver_id, amount) in mints.iter() {
+            self.internal_mint(receiver_id.as_ref(), amount.0);
+        }
+        let events: Vec<FtMint> = mints
+            .iter()
+            .map(|(receiver_id, amount)| FtMint {
+                owner_id: receiver_id.as_ref(),
+                amount: *amount,
+                memo: memo.as_deref(),
+            })
+            .collect();
+        FtMint::emit_many(&events);
+    }
+}
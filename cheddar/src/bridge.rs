@@ -0,0 +1,168 @@
+//! Bridge support: burning tokens with a destination on another chain (eg Aurora or
+//! Ethereum) and minting tokens deposited on another chain.
+//! Every bridge withdrawal gets a monotonically increasing nonce and is emitted as
+//! a `bridge_withdrawal` event, which relayers index. Withdrawals are not stored.
+//! Bridge deposits are identified by the source chain and a deposit id (eg the source chain
+//! nonce or transaction hash) and can be minted only once. Bridge minters have their own
+//! mint allowance and period cap, separate from the minter allowance.
+
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+use crate::events::{BridgeDeposit, BridgeWithdrawal, FtMint};
+use crate::util::*;
+use crate::*;
+
+/// Maximum length of the destination chain name.
+const MAX_CHAIN_LEN: usize = 32;
+/// Maximum length of the recipient address and the deposit id.
+const MAX_ADDRESS_LEN: usize = 128;
+
+/// Key of the processed deposits set.
+fn deposit_key(source_chain: &str, deposit_id: &str) -> Vec<u8> {
+    let key = (source_chain.to_string(), deposit_id.to_string());
+    env::sha256(&key.try_to_vec().unwrap())
+}
+
+fn assert_bridge_param(name: &str, value: &str, max_len: usize) {
+    assert!(
+        !value.is_empty() && value.len() <= max_len,
+        "{} must be non empty and at most {} characters long",
+        name,
+        max_len
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Burns `amount` of the caller tokens to be released to the `recipient` on the
+    /// `destination_chain`. Returns the withdrawal nonce.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn ft_burn_for_bridge(
+        &mut self,
+        amount: U128String,
+        destination_chain: String,
+        recipient: String,
+    ) -> U64String {
+        assert_one_yocto();
+        assert_bridge_param("destination_chain", &destination_chain, MAX_CHAIN_LEN);
+        assert_bridge_param("recipient", &recipient, MAX_ADDRESS_LEN);
+        let account_id = env::predecessor_account_id();
+        self.internal_burn(&account_id, amount.0, Some("bridge"));
+
+        let nonce = self.next_bridge_nonce;
+        self.next_bridge_nonce += 1;
+        BridgeWithdrawal {
+            nonce: nonce.into(),
+            account_id: &account_id,
+            amount,
+            destination_chain: &destination_chain,
+            recipient: &recipient,
+        }
+        .emit();
+        nonce.into()
+    }
+
+    /// Mints tokens deposited on the `source_chain`. `deposit_id` identifies the deposit
+    /// on the source chain; each deposit can be minted only once.
+    /// The amount is deducted from the caller bridge minter allowance.
+    /// Only bridge minters can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn bridge_mint(
+        &mut self,
+        receiver_id: ValidAccountId,
+        amount: U128String,
+        source_chain: String,
+        deposit_id: String,
+    ) {
+        assert_one_yocto();
+        self.assert_role(Role::BridgeMinter);
+        assert_bridge_param("source_chain", &source_chain, MAX_CHAIN_LEN);
+        assert_bridge_param("deposit_id", &deposit_id, MAX_ADDRESS_LEN);
+        assert!(
+            self.bridge_deposits
+                .insert(&deposit_key(&source_chain, &deposit_id)),
+            "bridge deposit already minted"
+        );
+        let minter_id = env::predecessor_account_id();
+        let mut a = self
+            .bridge_minter_allowances
+            .get(&minter_id)
+            .expect("not a bridge minter");
+        a.consume(amount.0);
+        self.bridge_minter_allowances.insert(&minter_id, &a);
+        self.internal_record_minted(&minter_id, amount.0);
+        self.internal_mint(receiver_id.as_ref(), amount.0);
+        FtMint {
+            owner_id: receiver_id.as_ref(),
+            amount,
+            memo: Some("bridge"),
+        }
+        .emit();
+        BridgeDeposit {
+            source_chain: &source_chain,
+            deposit_id: &deposit_id,
+            receiver_id: receiver_id.as_ref(),
+            amount,
+        }
+        .emit();
+    }
+
+    /// Sets the remaining total amount the bridge minter is allowed to mint.
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn set_bridge_minter_allowance(&mut self, account_id: AccountId, allowance: U128String) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        let mut a = self
+            .bridge_minter_allowances
+            .get(&account_id)
+            .expect("not a bridge minter");
+        a.allowance = allowance.0;
+        self.bridge_minter_allowances.insert(&account_id, &a);
+    }
+
+    /// Sets the maximum amount the bridge minter can mint within a single period of
+    /// `period_duration` nanoseconds. Removes the cap if `period_cap` is None.
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn set_bridge_minter_period_cap(
+        &mut self,
+        account_id: AccountId,
+        period_cap: Option<U128String>,
+        period_duration: U64String,
+    ) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        let mut a = self
+            .bridge_minter_allowances
+            .get(&account_id)
+            .expect("not a bridge minter");
+        a.set_period_cap(period_cap.map(|c| c.0), period_duration.0);
+        self.bridge_minter_allowances.insert(&account_id, &a);
+    }
+
+    /// Returns the bridge minter allowance. Returns None if the account is not
+    /// a bridge minter.
+    pub fn get_bridge_minter_allowance(
+        &self,
+        account_id: AccountId,
+    ) -> Option<MinterAllowanceJSON> {
+        self.bridge_minter_allowances
+            .get(&account_id)
+            .map(|a| a.to_json())
+    }
+
+    /// Returns the nonce which will be assigned to the next bridge withdrawal.
+    pub fn get_next_bridge_nonce(&self) -> U64String {
+        self.next_bridge_nonce.into()
+    }
+
+    /// Returns true if the deposit was already minted.
+    pub fn is_bridge_deposit_minted(&self, source_chain: String, deposit_id: String) -> bool {
+        self.bridge_deposits
+            .contains(&deposit_key(&source_chain, &deposit_id))
+    }
+}
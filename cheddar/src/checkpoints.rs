@@ -0,0 +1,315 @@
+//! Balance and total supply checkpoints for historical queries (eg DAO voting power
+//! at a proposal snapshot).
+//! A checkpoint is recorded whenever a balance changes. Multiple changes in the same block
+//! are squashed into one checkpoint.
+//!
+//! Balance history is opt-in: an account enables it with `enable_balance_history`,
+//! attaching a storage deposit for a fixed number of checkpoints. When the history is full,
+//! the oldest checkpoint is pruned. The deposit is refunded when the history is disabled
+//! or the account is closed. The total supply history is paid by the contract and keeps
+//! at most `SUPPLY_HISTORY_CAPACITY` checkpoints.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, BlockHeight, Promise};
+
+use crate::util::*;
+use crate::vesting::Timestamp;
+use crate::*;
+
+/// Maximum number of checkpoints kept in an account history. Bounds the gas needed to
+/// remove the history.
+pub const MAX_HISTORY_CAPACITY: u64 = 500;
+/// Number of total supply checkpoints kept by the contract.
+pub const SUPPLY_HISTORY_CAPACITY: u64 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Checkpoint {
+    /// block height when the value was set.
+    pub block: BlockHeight,
+    /// block timestamp in nanoseconds when the value was set.
+    pub timestamp: Timestamp,
+    /// value (balance) since `block`.
+    pub value: Balance,
+}
+
+/// Bounded list of checkpoints: a ring buffer of at most `capacity` checkpoints. When it's
+/// full, a new checkpoint overwrites the oldest one.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct History {
+    checkpoints: Vector<Checkpoint>,
+    /// index of the oldest checkpoint in `checkpoints`.
+    head: u64,
+    pub capacity: u64,
+    /// NEAR deposited to cover the history storage, refunded when the history is removed.
+    pub deposit: Balance,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct HistoryJSON {
+    pub capacity: u64,
+    pub len: u64,
+    pub deposit: U128String,
+    /// block of the oldest recorded checkpoint. Values before this block are not available.
+    pub oldest_block: Option<U64String>,
+}
+
+impl History {
+    pub fn new(prefix: Vec<u8>, capacity: u64, deposit: Balance) -> Self {
+        assert!(capacity > 0, "history capacity must be positive");
+        Self {
+            checkpoints: Vector::new(prefix),
+            head: 0,
+            capacity,
+            deposit,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.checkpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+
+    /// Returns the i-th checkpoint, starting from the oldest one.
+    fn get(&self, i: u64) -> Checkpoint {
+        self.checkpoints
+            .get((self.head + i) % self.checkpoints.len())
+            .unwrap()
+    }
+
+    pub fn last(&self) -> Option<Checkpoint> {
+        match self.len() {
+            0 => None,
+            n => Some(self.get(n - 1)),
+        }
+    }
+
+    /// Records `value` at the current block.
+    pub fn push(&mut self, value: Balance) {
+        let c = Checkpoint {
+            block: env::block_index(),
+            timestamp: env::block_timestamp(),
+            value,
+        };
+        let len = self.len();
+        if len > 0 && self.get(len - 1).block == c.block {
+            let last = (self.head + len - 1) % len;
+            self.checkpoints.replace(last, &c);
+        } else if len < self.capacity {
+            self.checkpoints.push(&c);
+        } else {
+            // full: overwrite the oldest checkpoint
+            self.checkpoints.replace(self.head, &c);
+            self.head = (self.head + 1) % len;
+        }
+    }
+
+    /// Returns the value at the end of the block `at` (or at the timestamp `at` if
+    /// `by_timestamp` is true) using binary search over the checkpoints.
+    /// Returns None if `at` is before the oldest checkpoint.
+    pub fn value_at(&self, at: u64, by_timestamp: bool) -> Option<Balance> {
+        let key = |c: &Checkpoint| if by_timestamp { c.timestamp } else { c.block };
+        // find the first checkpoint after `at`
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if key(&self.get(mid)) > at {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        if low == 0 {
+            None
+        } else {
+            Some(self.get(low - 1).value)
+        }
+    }
+
+    /// Removes all checkpoints.
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+        self.head = 0;
+    }
+
+    pub fn to_json(&self) -> HistoryJSON {
+        HistoryJSON {
+            capacity: self.capacity,
+            len: self.len(),
+            deposit: self.deposit.into(),
+            oldest_block: if self.len() > 0 {
+                Some(self.get(0).block.into())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Storage prefix of the account history.
+pub(crate) fn account_history_prefix(prefix: &[u8], account_id: &AccountId) -> Vec<u8> {
+    let mut p = prefix.to_vec();
+    p.extend(env::sha256(account_id.as_bytes()));
+    p
+}
+
+/// Removes the account history and returns its deposit.
+pub(crate) fn remove_history(
+    histories: &mut LookupMap<AccountId, History>,
+    account_id: &AccountId,
+) -> Balance {
+    match histories.remove(account_id) {
+        Some(mut h) => {
+            h.clear();
+            h.deposit
+        }
+        None => 0,
+    }
+}
+
+/// Unwraps a historical value, panics if the value is older than the history.
+pub(crate) fn expect_value(value: Option<Balance>, history: &History) -> Balance {
+    match value {
+        Some(v) => v,
+        None => env::panic(
+            format!(
+                "history is not available before block {}",
+                history.to_json().oldest_block.map_or(0, |b| b.0)
+            )
+            .as_bytes(),
+        ),
+    }
+}
+
+impl Contract {
+    /// NEAR deposit required for an account history with `capacity` checkpoints.
+    pub(crate) fn history_storage_cost(&self, capacity: u64) -> Balance {
+        (self.history_storage_usage + capacity * self.checkpoint_storage_usage) as Balance
+            * env::storage_byte_cost()
+    }
+
+    /// Creates a new history of the caller, paid by the attached deposit. The unused
+    /// deposit is refunded.
+    pub(crate) fn internal_new_history(&self, prefix: &[u8], capacity: u64) -> History {
+        assert!(
+            capacity > 0 && capacity <= MAX_HISTORY_CAPACITY,
+            "history capacity must be between 1 and {}",
+            MAX_HISTORY_CAPACITY
+        );
+        let cost = self.history_storage_cost(capacity);
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= cost,
+            "attached deposit: {},  required: {}",
+            deposit,
+            cost
+        );
+        if deposit > cost {
+            Promise::new(env::predecessor_account_id()).transfer(deposit - cost);
+        }
+        History::new(
+            account_history_prefix(prefix, &env::predecessor_account_id()),
+            capacity,
+            cost,
+        )
+    }
+
+    /// Records a new balance of the account if the account has the balance history enabled.
+    pub(crate) fn record_balance_checkpoint(&mut self, account_id: &AccountId, balance: Balance) {
+        if let Some(mut h) = self.balance_checkpoints.get(account_id) {
+            h.push(balance);
+            self.balance_checkpoints.insert(account_id, &h);
+        }
+    }
+
+    /// Records the current total supply.
+    pub(crate) fn record_supply_checkpoint(&mut self) {
+        self.supply_checkpoints.push(self.total_supply);
+    }
+
+    fn internal_balance_at(&self, account_id: &AccountId, at: u64, by_timestamp: bool) -> Balance {
+        let h = self.balance_checkpoints.get(account_id).unwrap_or_else(|| {
+            env::panic(format!("balance history of {} is not enabled", account_id).as_bytes())
+        });
+        expect_value(h.value_at(at, by_timestamp), &h)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Enables the caller balance history, keeping up to `capacity` checkpoints.
+    /// Requires a storage deposit (see `get_history_storage_cost`), unused deposit is
+    /// refunded.
+    #[payable]
+    pub fn enable_balance_history(&mut self, capacity: u64) {
+        let account_id = env::predecessor_account_id();
+        let balance = self._must_balance_of(&account_id).token;
+        assert!(
+            !self.balance_checkpoints.contains_key(&account_id),
+            "balance history is already enabled"
+        );
+        let mut h = self.internal_new_history(b"C", capacity);
+        h.push(balance);
+        self.balance_checkpoints.insert(&account_id, &h);
+    }
+
+    /// Removes the caller balance history and refunds its storage deposit.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn disable_balance_history(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.balance_checkpoints.contains_key(&account_id),
+            "balance history is not enabled"
+        );
+        let deposit = remove_history(&mut self.balance_checkpoints, &account_id);
+        Promise::new(account_id).transfer(deposit + 1);
+    }
+
+    /// Returns the NEAR deposit required for a history with `capacity` checkpoints.
+    pub fn get_history_storage_cost(&self, capacity: u64) -> U128String {
+        self.history_storage_cost(capacity).into()
+    }
+
+    pub fn get_balance_history(&self, account_id: ValidAccountId) -> Option<HistoryJSON> {
+        self.balance_checkpoints
+            .get(account_id.as_ref())
+            .map(|h| h.to_json())
+    }
+
+    /// Returns the account balance at the end of the given block.
+    /// Panics if the account balance history is not enabled or the block is older than
+    /// the history.
+    pub fn ft_balance_at(&self, account_id: ValidAccountId, block: U64String) -> U128String {
+        self.internal_balance_at(account_id.as_ref(), block.0, false)
+            .into()
+    }
+
+    /// Returns the account balance at the given timestamp (in nanoseconds).
+    pub fn ft_balance_at_timestamp(
+        &self,
+        account_id: ValidAccountId,
+        timestamp: U64String,
+    ) -> U128String {
+        self.internal_balance_at(account_id.as_ref(), timestamp.0, true)
+            .into()
+    }
+
+    /// Returns the total supply at the end of the given block.
+    pub fn ft_total_supply_at(&self, block: U64String) -> U128String {
+        let h = &self.supply_checkpoints;
+        expect_value(h.value_at(block.0, false), h).into()
+    }
+
+    /// Returns the total supply at the given timestamp (in nanoseconds).
+    pub fn ft_total_supply_at_timestamp(&self, timestamp: U64String) -> U128String {
+        let h = &self.supply_checkpoints;
+        expect_value(h.value_at(timestamp.0, true), h).into()
+    }
+}
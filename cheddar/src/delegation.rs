@@ -0,0 +1,216 @@
+//! Compound style vote delegation.
+//! A holder delegates its voting power to a delegatee (possibly itself). Votes of a delegatee
+//! are the sum of the balances of all accounts delegating to it. Tokens locked in vesting
+//! grants are counted only if the delegator opts in with `include_vesting`. Otherwise the
+//! last recorded vesting lock is subtracted; it's updated whenever the delegator balance or
+//! vesting changes (or with `refresh_vesting_locks`). Accounts without a delegate don't vote.
+//!
+//! Vote history (needed by `get_prior_votes`) is opt-in: a delegatee enables it with
+//! `enable_vote_history`, attaching a storage deposit, the same way as the balance history.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Promise};
+
+use crate::checkpoints::{expect_value, remove_history, HistoryJSON};
+use crate::events::DelegateChanged;
+use crate::util::*;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Delegation {
+    pub delegatee: AccountId,
+    /// if true, tokens locked in vesting grants count toward the voting power.
+    pub include_vesting: bool,
+    /// votes currently delegated to the delegatee.
+    pub votes: Balance,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DelegationJSON {
+    pub delegatee: AccountId,
+    pub include_vesting: bool,
+    pub votes: U128String,
+}
+
+impl Contract {
+    /// Returns the voting power of the account.
+    fn delegated_weight(&self, account_id: &AccountId, include_vesting: bool) -> Balance {
+        let balance = self._balance_of(account_id);
+        if include_vesting {
+            balance
+        } else {
+            balance.saturating_sub(self.vesting_locked.get(account_id).unwrap_or(0))
+        }
+    }
+
+    /// Moves the delegated votes of the account when its balance or vesting lock changes.
+    pub(crate) fn internal_refresh_delegated_votes(&mut self, account_id: &AccountId) {
+        if let Some(mut d) = self.delegates.get(account_id) {
+            let votes = self.delegated_weight(account_id, d.include_vesting);
+            if votes == d.votes {
+                return;
+            }
+            let current = self.internal_current_votes(&d.delegatee);
+            self.internal_set_votes(&d.delegatee, current - d.votes + votes);
+            d.votes = votes;
+            self.delegates.insert(account_id, &d);
+        }
+    }
+
+    /// Sets (or removes if `delegatee` is None) the delegate of the account and moves
+    /// the account votes from the previous delegate to the new one.
+    pub(crate) fn internal_delegate(
+        &mut self,
+        account_id: &AccountId,
+        delegatee: Option<AccountId>,
+        include_vesting: bool,
+    ) {
+        let previous = self.delegates.remove(account_id);
+        if let Some(p) = &previous {
+            let votes = self.internal_current_votes(&p.delegatee) - p.votes;
+            self.internal_set_votes(&p.delegatee, votes);
+        }
+        if let Some(d) = &delegatee {
+            let votes = self.delegated_weight(account_id, include_vesting);
+            self.internal_set_votes(d, self.internal_current_votes(d) + votes);
+            self.delegates.insert(
+                account_id,
+                &Delegation {
+                    delegatee: d.clone(),
+                    include_vesting,
+                    votes,
+                },
+            );
+        }
+        let previous = previous.map(|p| p.delegatee);
+        if previous != delegatee {
+            DelegateChanged {
+                delegator_id: account_id,
+                from_delegate: previous.as_ref(),
+                to_delegate: delegatee.as_ref(),
+            }
+            .emit();
+        }
+    }
+
+    /// Sets the current votes of the delegatee and records them in its vote history.
+    fn internal_set_votes(&mut self, account_id: &AccountId, votes: Balance) {
+        if votes == 0 {
+            self.votes.remove(account_id);
+        } else {
+            self.votes.insert(account_id, &votes);
+        }
+        if let Some(mut h) = self.vote_checkpoints.get(account_id) {
+            h.push(votes);
+            self.vote_checkpoints.insert(account_id, &h);
+        }
+    }
+
+    fn internal_current_votes(&self, account_id: &AccountId) -> Balance {
+        self.votes.get(account_id).unwrap_or(0)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Delegates the caller voting power to `delegatee`. Use the own account to vote directly.
+    /// Tokens locked in vesting grants count only if `include_vesting` is true
+    /// (default false). Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn delegate(&mut self, delegatee: ValidAccountId, include_vesting: Option<bool>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.accounts.contains_key(&account_id),
+            "account {} is not registered",
+            account_id
+        );
+        self.internal_update_locked(&account_id);
+        self.internal_delegate(
+            &account_id,
+            Some(delegatee.into()),
+            include_vesting.unwrap_or(false),
+        );
+    }
+
+    /// Removes the caller delegation. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn undelegate(&mut self) {
+        assert_one_yocto();
+        self.internal_delegate(&env::predecessor_account_id(), None, false);
+    }
+
+    /// Enables the caller vote history, keeping up to `capacity` checkpoints.
+    /// Requires a storage deposit (see `get_history_storage_cost`), unused deposit is
+    /// refunded.
+    #[payable]
+    pub fn enable_vote_history(&mut self, capacity: u64) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            !self.vote_checkpoints.contains_key(&account_id),
+            "vote history is already enabled"
+        );
+        let mut h = self.internal_new_history(b"O", capacity);
+        h.push(self.internal_current_votes(&account_id));
+        self.vote_checkpoints.insert(&account_id, &h);
+    }
+
+    /// Removes the caller vote history and refunds its storage deposit.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn disable_vote_history(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.vote_checkpoints.contains_key(&account_id),
+            "vote history is not enabled"
+        );
+        let deposit = remove_history(&mut self.vote_checkpoints, &account_id);
+        Promise::new(account_id).transfer(deposit + 1);
+    }
+
+    /// Returns the delegate of the account.
+    pub fn delegates(&self, account_id: ValidAccountId) -> Option<AccountId> {
+        self.delegates.get(account_id.as_ref()).map(|d| d.delegatee)
+    }
+
+    pub fn get_delegation(&self, account_id: ValidAccountId) -> Option<DelegationJSON> {
+        self.delegates
+            .get(account_id.as_ref())
+            .map(|d| DelegationJSON {
+                delegatee: d.delegatee,
+                include_vesting: d.include_vesting,
+                votes: d.votes.into(),
+            })
+    }
+
+    pub fn get_vote_history(&self, account_id: ValidAccountId) -> Option<HistoryJSON> {
+        self.vote_checkpoints
+            .get(account_id.as_ref())
+            .map(|h| h.to_json())
+    }
+
+    /// Returns the current voting power of the account.
+    pub fn get_current_votes(&self, account_id: ValidAccountId) -> U128String {
+        self.internal_current_votes(account_id.as_ref()).into()
+    }
+
+    /// Returns the voting power of the account at the end of the given block.
+    /// The block must be already finalized (lower than the current block).
+    /// Panics if the account vote history is not enabled or the block is older than
+    /// the history.
+    pub fn get_prior_votes(&self, account_id: ValidAccountId, block: U64String) -> U128String {
+        assert!(block.0 < env::block_index(), "votes are not yet determined");
+        let h = self
+            .vote_checkpoints
+            .get(account_id.as_ref())
+            .unwrap_or_else(|| {
+                env::panic(
+                    format!("vote history of {} is not enabled", account_id.as_ref()).as_bytes(),
+                )
+            });
+        expect_value(h.value_at(block.0, false), &h).into()
+    }
+}
@@ -0,0 +1,314 @@
+//! NEP-297 structured events.
+//! Every event is logged as `EVENT_JSON:` followed by the JSON serialized event.
+
+use near_sdk::serde::Serialize;
+use near_sdk::{log, serde_json, AccountId};
+
+use crate::fee::TransferFeeJSON;
+use crate::pause::PauseFeature;
+use crate::roles::Role;
+use crate::util::*;
+
+const NEP141_STANDARD: &str = "nep141";
+const NEP141_VERSION: &str = "1.0.0";
+/// Standard name for the Cheddar specific events.
+const CHEDDAR_STANDARD: &str = "cheddar";
+const CHEDDAR_VERSION: &str = "1.0.0";
+
+#[derive(Serialize)]
+struct EventLog<'a, T: Serialize> {
+    standard: &'a str,
+    version: &'a str,
+    event: &'a str,
+    data: T,
+}
+
+/// Logs a NEP-297 event.
+pub(crate) fn emit_event<T: Serialize>(standard: &str, version: &str, event: &str, data: T) {
+    let e = EventLog {
+        standard,
+        version,
+        event,
+        data,
+    };
+    log!("EVENT_JSON:{}", serde_json::to_string(&e).unwrap());
+}
+
+/// NEP-141 `ft_mint` event data.
+#[derive(Serialize)]
+pub struct FtMint<'a> {
+    pub owner_id: &'a AccountId,
+    pub amount: U128String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl FtMint<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[FtMint<'_>]) {
+        emit_event(NEP141_STANDARD, NEP141_VERSION, "ft_mint", data)
+    }
+}
+
+/// NEP-141 `ft_burn` event data.
+#[derive(Serialize)]
+pub struct FtBurn<'a> {
+    pub owner_id: &'a AccountId,
+    pub amount: U128String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl FtBurn<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[FtBurn<'_>]) {
+        emit_event(NEP141_STANDARD, NEP141_VERSION, "ft_burn", data)
+    }
+}
+
+/// NEP-141 `ft_transfer` event data.
+#[derive(Serialize)]
+pub struct FtTransfer<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    pub amount: U128String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+impl FtTransfer<'_> {
+    pub fn emit(self) {
+        Self::emit_many(&[self])
+    }
+
+    pub fn emit_many(data: &[FtTransfer<'_>]) {
+        emit_event(NEP141_STANDARD, NEP141_VERSION, "ft_transfer", data)
+    }
+}
+
+/// Emitted when the owner cancels a vesting grant. `reclaimed` tokens were transferred
+/// to the `treasury_id` or burned if `treasury_id` is None.
+#[derive(Serialize)]
+pub struct VestingCancelled<'a> {
+    pub account_id: &'a AccountId,
+    pub grant_id: u64,
+    pub reclaimed: U128String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub treasury_id: Option<&'a AccountId>,
+}
+
+impl VestingCancelled<'_> {
+    pub fn emit(self) {
+        emit_event(
+            CHEDDAR_STANDARD,
+            CHEDDAR_VERSION,
+            "vesting_cancelled",
+            &[self],
+        )
+    }
+}
+
+/// Emitted when an owner sets or removes (amount = 0) an allowance for a spender.
+#[derive(Serialize)]
+pub struct FtApprove<'a> {
+    pub owner_id: &'a AccountId,
+    pub spender_id: &'a AccountId,
+    pub amount: U128String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<U64String>,
+}
+
+impl FtApprove<'_> {
+    pub fn emit(self) {
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, "ft_approve", &[self])
+    }
+}
+
+/// Emitted as `role_granted` or `role_revoked` event.
+#[derive(Serialize)]
+pub struct RoleChanged<'a> {
+    pub role: Role,
+    pub account_id: &'a AccountId,
+    #[serde(skip)]
+    pub granted: bool,
+}
+
+impl RoleChanged<'_> {
+    pub fn emit(self) {
+        let event = if self.granted {
+            "role_granted"
+        } else {
+            "role_revoked"
+        };
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, event, &[self])
+    }
+}
+
+/// Ownership transfer events: `ownership_transfer_proposed`, `ownership_transfer_cancelled`
+/// and `ownership_transferred`.
+#[derive(Serialize)]
+pub struct OwnershipChanged<'a> {
+    #[serde(skip)]
+    pub event: &'a str,
+    pub owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_after: Option<U64String>,
+}
+
+impl OwnershipChanged<'_> {
+    pub fn emit(self) {
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, self.event, &[self])
+    }
+}
+
+/// Emitted as `pause` or `unpause` event.
+#[derive(Serialize)]
+pub struct PauseChanged<'a> {
+    pub feature: PauseFeature,
+    #[serde(skip)]
+    pub paused: bool,
+    pub account_id: &'a AccountId,
+}
+
+impl PauseChanged<'_> {
+    pub fn emit(self) {
+        let event = if self.paused { "pause" } else { "unpause" };
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, event, &[self])
+    }
+}
+
+/// Emitted when an account changes its delegate. `to_delegate` is None when the
+/// delegation is removed.
+#[derive(Serialize)]
+pub struct DelegateChanged<'a> {
+    pub delegator_id: &'a AccountId,
+    pub from_delegate: Option<&'a AccountId>,
+    pub to_delegate: Option<&'a AccountId>,
+}
+
+impl DelegateChanged<'_> {
+    pub fn emit(self) {
+        emit_event(
+            CHEDDAR_STANDARD,
+            CHEDDAR_VERSION,
+            "delegate_changed",
+            &[self],
+        )
+    }
+}
+
+/// Airdrop campaign events: `airdrop_created` (`amount` is the budget) and
+/// `airdrop_reclaimed` (`amount` is the unclaimed budget).
+#[derive(Serialize)]
+pub struct AirdropEvent<'a> {
+    #[serde(skip)]
+    pub event: &'a str,
+    pub campaign_id: u64,
+    pub amount: U128String,
+}
+
+impl AirdropEvent<'_> {
+    pub fn emit(self) {
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, self.event, &[self])
+    }
+}
+
+/// Staged upgrade events: `upgrade_staged`, `upgrade_cancelled` and `upgrade_deployed`.
+#[derive(Serialize)]
+pub struct UpgradeEvent<'a> {
+    #[serde(skip)]
+    pub event: &'a str,
+    /// hex encoded sha256 of the code.
+    pub code_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deploy_after: Option<U64String>,
+}
+
+impl UpgradeEvent<'_> {
+    pub fn emit(self) {
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, self.event, &[self])
+    }
+}
+
+/// Emitted when tokens are burned to be released on another chain. Relayers index this event.
+#[derive(Serialize)]
+pub struct BridgeWithdrawal<'a> {
+    pub nonce: U64String,
+    pub account_id: &'a AccountId,
+    pub amount: U128String,
+    pub destination_chain: &'a str,
+    pub recipient: &'a str,
+}
+
+impl BridgeWithdrawal<'_> {
+    pub fn emit(self) {
+        emit_event(
+            CHEDDAR_STANDARD,
+            CHEDDAR_VERSION,
+            "bridge_withdrawal",
+            &[self],
+        )
+    }
+}
+
+/// Emitted when tokens deposited on another chain are minted.
+#[derive(Serialize)]
+pub struct BridgeDeposit<'a> {
+    pub source_chain: &'a str,
+    pub deposit_id: &'a str,
+    pub receiver_id: &'a AccountId,
+    pub amount: U128String,
+}
+
+impl BridgeDeposit<'_> {
+    pub fn emit(self) {
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, "bridge_deposit", &[self])
+    }
+}
+
+/// Emitted as `account_frozen` or, when `frozen_until` is None, `account_unfrozen` event.
+#[derive(Serialize)]
+pub struct FreezeChanged<'a> {
+    pub account_id: &'a AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frozen_until: Option<U64String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'a str>,
+    /// admin or guardian who changed the freeze.
+    pub by: &'a AccountId,
+}
+
+impl FreezeChanged<'_> {
+    pub fn emit(self) {
+        let event = if self.frozen_until.is_some() {
+            "account_frozen"
+        } else {
+            "account_unfrozen"
+        };
+        emit_event(CHEDDAR_STANDARD, CHEDDAR_VERSION, event, &[self])
+    }
+}
+
+/// Emitted when the transfer fee is set or removed (`fee` is None).
+#[derive(Serialize)]
+pub struct TransferFeeChanged {
+    pub fee: Option<TransferFeeJSON>,
+}
+
+impl TransferFeeChanged {
+    pub fn emit(self) {
+        emit_event(
+            CHEDDAR_STANDARD,
+            CHEDDAR_VERSION,
+            "transfer_fee_changed",
+            &[self],
+        )
+    }
+}
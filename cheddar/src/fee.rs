@@ -0,0 +1,203 @@
+//! Optional protocol fee on transfers. The fee is a part of the transferred amount (in basis
+//! points, capped by `max_fee`), deducted from the amount credited to the receiver and
+//! credited to the treasury. Transfers from or to exempted accounts (eg farms, DEX pools and
+//! minters), the treasury and the token contract itself are not charged. Internal moves
+//! (vesting reclaims and account closure sweeps) are not charged either.
+//! `ft_transfer_call` reports the fee as used: it's not refunded to the sender.
+//! Every charged fee is reported as an additional `ft_transfer` event entry to the treasury
+//! with the "transfer fee" memo.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance};
+
+use crate::events::TransferFeeChanged;
+use crate::util::*;
+use crate::vesting::BASIS_POINTS;
+use crate::*;
+
+/// Maximum transfer fee: 5%.
+pub const MAX_TRANSFER_FEE_BPS: u16 = 500;
+/// Maximum number of exempted accounts returned in one page.
+pub const MAX_FEE_EXEMPT_PAGE: u64 = 100;
+pub const TRANSFER_FEE_MEMO: &str = "transfer fee";
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TransferFee {
+    /// fee in basis points of the transferred amount.
+    pub fee_bps: u16,
+    /// maximum fee charged for a single transfer.
+    pub max_fee: Balance,
+    /// account credited with the fees. Must be registered.
+    pub treasury_id: AccountId,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TransferFeeJSON {
+    pub fee_bps: u16,
+    pub max_fee: U128String,
+    pub treasury_id: AccountId,
+}
+
+impl Contract {
+    /// Returns the fee charged for transferring `amount` from `sender_id` to `receiver_id`.
+    pub(crate) fn internal_transfer_fee(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Balance {
+        let fee = match &self.transfer_fee {
+            Some(f) => f,
+            None => return 0,
+        };
+        let contract_id = env::current_account_id();
+        if *sender_id == fee.treasury_id
+            || *receiver_id == fee.treasury_id
+            || *sender_id == contract_id
+            || *receiver_id == contract_id
+            || self.fee_exempt.contains(sender_id)
+            || self.fee_exempt.contains(receiver_id)
+        {
+            return 0;
+        }
+        std::cmp::min(
+            fraction_of(amount, fee.fee_bps as u128, BASIS_POINTS),
+            fee.max_fee,
+        )
+    }
+
+    /// Moves `amount` from the sender, crediting the receiver with the amount reduced by
+    /// the transfer fee and the treasury with the fee. Doesn't emit events.
+    /// Returns `(amount credited to the receiver, fee)`.
+    pub(crate) fn internal_move_balance_with_fee(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> (Balance, Balance) {
+        let fee = self.internal_transfer_fee(sender_id, receiver_id, amount);
+        let net = amount - fee;
+        self.internal_move_balance(sender_id, receiver_id, net);
+        if fee > 0 {
+            let treasury_id = self.fee_treasury().unwrap().clone();
+            self.internal_move_balance(sender_id, &treasury_id, fee);
+        }
+        (net, fee)
+    }
+
+    /// Returns the fee treasury if a transfer fee is set.
+    pub(crate) fn fee_treasury(&self) -> Option<&AccountId> {
+        self.transfer_fee.as_ref().map(|f| &f.treasury_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the transfer fee (at most `MAX_TRANSFER_FEE_BPS`), replacing the previous one.
+    /// The treasury must be registered. Only admins can call this function.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn set_transfer_fee(
+        &mut self,
+        fee_bps: u16,
+        max_fee: U128String,
+        treasury_id: ValidAccountId,
+    ) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            fee_bps > 0 && fee_bps <= MAX_TRANSFER_FEE_BPS,
+            "transfer fee must be positive and at most {} basis points",
+            MAX_TRANSFER_FEE_BPS
+        );
+        assert!(max_fee.0 > 0, "max fee must be positive");
+        assert!(
+            self.accounts.contains_key(treasury_id.as_ref()),
+            "treasury account is not registered"
+        );
+        self.transfer_fee = Some(TransferFee {
+            fee_bps,
+            max_fee: max_fee.0,
+            treasury_id: treasury_id.into(),
+        });
+        TransferFeeChanged {
+            fee: self.get_transfer_fee(),
+        }
+        .emit();
+    }
+
+    /// Removes the transfer fee. Only admins can call this function.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn remove_transfer_fee(&mut self) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            self.transfer_fee.take().is_some(),
+            "transfer fee is not set"
+        );
+        TransferFeeChanged { fee: None }.emit();
+    }
+
+    /// Exempts the account from the transfer fee (both as a sender and as a receiver).
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn add_fee_exempt_account(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            self.fee_exempt.insert(account_id.as_ref()),
+            "account is already exempted"
+        );
+    }
+
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn remove_fee_exempt_account(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            self.fee_exempt.remove(account_id.as_ref()),
+            "account is not exempted"
+        );
+    }
+
+    pub fn get_transfer_fee(&self) -> Option<TransferFeeJSON> {
+        self.transfer_fee.as_ref().map(|f| TransferFeeJSON {
+            fee_bps: f.fee_bps,
+            max_fee: f.max_fee.into(),
+            treasury_id: f.treasury_id.clone(),
+        })
+    }
+
+    pub fn is_fee_exempt(&self, account_id: ValidAccountId) -> bool {
+        self.fee_exempt.contains(account_id.as_ref())
+    }
+
+    pub fn get_fee_exempt_accounts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<AccountId> {
+        let from_index = from_index.unwrap_or(0);
+        let limit = std::cmp::min(limit.unwrap_or(MAX_FEE_EXEMPT_PAGE), MAX_FEE_EXEMPT_PAGE);
+        let accounts = self.fee_exempt.as_vector();
+        let to_index = std::cmp::min(from_index.saturating_add(limit), accounts.len());
+        (from_index..to_index)
+            .map(|i| accounts.get(i).unwrap())
+            .collect()
+    }
+
+    /// Returns the fee which would be charged for the transfer.
+    pub fn ft_transfer_fee(
+        &self,
+        sender_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128String,
+    ) -> U128String {
+        self.internal_transfer_fee(sender_id.as_ref(), receiver_id.as_ref(), amount.0)
+            .into()
+    }
+}
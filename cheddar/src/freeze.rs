@@ -0,0 +1,107 @@
+//! Account freezes for incident response (eg to stop an exploiter from moving tokens).
+//! Admins and guardians (pausers) can freeze an account for a limited time. A frozen
+//! account can't send, burn or unregister tokens, and refunds from a frozen receiver of
+//! `ft_transfer_call` are skipped. Freezes expire automatically.
+
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+use crate::events::FreezeChanged;
+use crate::util::*;
+use crate::vesting::Timestamp;
+use crate::*;
+
+/// Maximum freeze duration in nanoseconds: 30 days. Can be extended by freezing again.
+pub const MAX_FREEZE_DURATION: Timestamp = 30 * 24 * 3600 * 1_000_000_000;
+
+impl Contract {
+    /// Returns the timestamp until which the account is frozen, or None if the account
+    /// is not frozen (or the freeze expired).
+    pub(crate) fn internal_frozen_until(&self, account_id: &AccountId) -> Option<Timestamp> {
+        self.frozen
+            .get(account_id)
+            .filter(|t| *t > env::block_timestamp())
+    }
+
+    #[inline]
+    pub(crate) fn internal_is_frozen(&self, account_id: &AccountId) -> bool {
+        self.internal_frozen_until(account_id).is_some()
+    }
+
+    /// Panics if the account is frozen.
+    pub(crate) fn assert_not_frozen(&self, account_id: &AccountId) {
+        if let Some(t) = self.internal_frozen_until(account_id) {
+            env::panic(format!("account {} is frozen until {}", account_id, t).as_bytes());
+        }
+    }
+
+    fn assert_freezer(&self) {
+        let caller = env::predecessor_account_id();
+        assert!(
+            self.internal_has_role(Role::Admin, &caller)
+                || self.internal_has_role(Role::Pauser, &caller),
+            "only admins and guardians can freeze accounts"
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Freezes the account for `duration` nanoseconds (at most `MAX_FREEZE_DURATION`),
+    /// replacing the previous freeze. Only admins and guardians can call this function.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn freeze_account(
+        &mut self,
+        account_id: ValidAccountId,
+        duration: U64String,
+        reason: Option<String>,
+    ) {
+        assert_one_yocto();
+        self.assert_freezer();
+        assert!(
+            duration.0 > 0 && duration.0 <= MAX_FREEZE_DURATION,
+            "freeze duration must be positive and at most {}",
+            MAX_FREEZE_DURATION
+        );
+        let frozen_until = env::block_timestamp() + duration.0;
+        self.frozen.insert(account_id.as_ref(), &frozen_until);
+        FreezeChanged {
+            account_id: account_id.as_ref(),
+            frozen_until: Some(frozen_until.into()),
+            reason: reason.as_deref(),
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    /// Removes the account freeze. Only admins and guardians can call this function.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn unfreeze_account(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_freezer();
+        assert!(
+            self.frozen.remove(account_id.as_ref()).is_some(),
+            "account is not frozen"
+        );
+        FreezeChanged {
+            account_id: account_id.as_ref(),
+            frozen_until: None,
+            reason: None,
+            by: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+
+    pub fn is_frozen(&self, account_id: ValidAccountId) -> bool {
+        self.internal_is_frozen(account_id.as_ref())
+    }
+
+    /// Returns the timestamp (in nanoseconds) until which the account is frozen, or None
+    /// if the account is not frozen.
+    pub fn get_frozen_until(&self, account_id: ValidAccountId) -> Option<U64String> {
+        self.internal_frozen_until(account_id.as_ref())
+            .map(|t| t.into())
+    }
+}
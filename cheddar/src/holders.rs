@@ -0,0 +1,131 @@
+//! Enumerable index of token holders (accounts with a positive balance).
+//!
+//! Contracts migrated from a version without the index start with an empty index: holders
+//! are added only when their balance changes. To index the remaining holders:
+//! 1. export all accounts with a positive balance from an indexer (eg accounts which received
+//!    tokens before the migration),
+//! 2. call `backfill_holders` with batches of these accounts (accounts with 0 balance and
+//!    already indexed accounts are skipped, so batches can overlap or be repeated),
+//! 3. call `finish_holders_backfill`.
+//! Until the backfill is finished `ft_holder_count` returns None and `ft_holders` pages
+//! may be incomplete.
+
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, near_bindgen, AccountId, Balance};
+
+use crate::util::*;
+use crate::*;
+
+/// Maximum number of holders returned by a single page.
+pub const MAX_HOLDERS_PAGE: u64 = 100;
+/// Maximum number of holders scanned by a single `ft_top_holders` call.
+pub const TOP_HOLDERS_SCAN_LIMIT: u64 = 1000;
+
+#[derive(Deserialize, Serialize)]
+pub struct HolderJSON {
+    pub account_id: AccountId,
+    pub balance: U128String,
+}
+
+impl Contract {
+    /// Updates the holder index when the account balance changes.
+    pub(crate) fn internal_update_holders(
+        &mut self,
+        account_id: &AccountId,
+        old_balance: Balance,
+        new_balance: Balance,
+    ) {
+        if old_balance == 0 && new_balance > 0 {
+            self.holders.insert(account_id);
+        } else if old_balance > 0 && new_balance == 0 {
+            self.holders.remove(account_id);
+        }
+    }
+
+    fn holder_json(&self, account_id: AccountId) -> HolderJSON {
+        HolderJSON {
+            balance: self._balance_of(&account_id).into(),
+            account_id,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the number of accounts with a positive balance, or None if the holder index
+    /// backfill is not finished.
+    pub fn ft_holder_count(&self) -> Option<u64> {
+        if self.holders_backfill_complete {
+            Some(self.holders.len())
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if all holders are indexed.
+    pub fn is_holders_backfill_complete(&self) -> bool {
+        self.holders_backfill_complete
+    }
+
+    /// Returns a page of token holders with their balances. The order is not stable:
+    /// it changes when holders are removed.
+    pub fn ft_holders(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<HolderJSON> {
+        let from_index = from_index.unwrap_or(0);
+        let limit = std::cmp::min(limit.unwrap_or(MAX_HOLDERS_PAGE), MAX_HOLDERS_PAGE);
+        let holders = self.holders.as_vector();
+        let to_index = std::cmp::min(from_index.saturating_add(limit), holders.len());
+        (from_index..to_index)
+            .map(|i| self.holder_json(holders.get(i).unwrap()))
+            .collect()
+    }
+
+    /// Returns up to `limit` holders with the biggest balances, sorted by balance (desc).
+    /// To stay within the view gas limit, at most `TOP_HOLDERS_SCAN_LIMIT` holders are
+    /// scanned, starting from `from_index`. For a big holder index, call it for consecutive
+    /// ranges and merge the results.
+    pub fn ft_top_holders(&self, limit: u64, from_index: Option<u64>) -> Vec<HolderJSON> {
+        let from_index = from_index.unwrap_or(0);
+        let holders = self.holders.as_vector();
+        let to_index = std::cmp::min(
+            from_index.saturating_add(TOP_HOLDERS_SCAN_LIMIT),
+            holders.len(),
+        );
+        let mut top: Vec<HolderJSON> = (from_index..to_index)
+            .map(|i| self.holder_json(holders.get(i).unwrap()))
+            .collect();
+        top.sort_by(|a, b| b.balance.0.cmp(&a.balance.0));
+        top.truncate(std::cmp::min(limit, MAX_HOLDERS_PAGE) as usize);
+        top
+    }
+
+    /// Adds accounts with a positive balance to the holder index and records their vesting
+    /// locks. Used to index holders which didn't change their balance since the holder index
+    /// was introduced.
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn backfill_holders(&mut self, account_ids: Vec<ValidAccountId>) -> u64 {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        for a in account_ids.iter() {
+            if self._balance_of(a.as_ref()) > 0 {
+                self.holders.insert(a.as_ref());
+                self.internal_update_locked(a.as_ref());
+            }
+        }
+        self.holders.len()
+    }
+
+    /// Marks the holder index backfill as finished.
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn finish_holders_backfill(&mut self) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            !self.holders_backfill_complete,
+            "holder index backfill is already finished"
+        );
+        self.holders_backfill_complete = true;
+    }
+}
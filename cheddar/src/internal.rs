@@ -1,6 +1,8 @@
 use near_sdk::json_types::{ValidAccountId, U128};
-use near_sdk::{AccountId, Balance, PromiseResult};
+use near_sdk::{AccountId, Balance, Promise, PromiseResult};
 
+use crate::events::{FtBurn, FtMint, FtTransfer};
+use crate::fee::TRANSFER_FEE_MEMO;
 use crate::storage::AccBalance;
 use crate::*;
 
@@ -12,9 +14,20 @@ impl Contract {
         );
     }
 
-    #[inline]
-    pub(crate) fn assert_minter(&self, account_id: String) {
-        assert!(self.minters.contains(&account_id), "not a minter");
+    /// returns minter allowance, panics if the account is not a minter
+    pub(crate) fn internal_get_minter_allowance(&self, account_id: &AccountId) -> MinterAllowance {
+        self.minter_allowances
+            .get(account_id)
+            .expect("not a minter")
+    }
+
+    /// Records `amount` minted by the `minter_id`.
+    /// Panics if the minter allowance or the period cap is exceeded.
+    pub(crate) fn consume_minter_allowance(&mut self, minter_id: &AccountId, amount: Balance) {
+        let mut a = self.internal_get_minter_allowance(minter_id);
+        a.consume(amount);
+        self.minter_allowances.insert(minter_id, &a);
+        self.internal_record_minted(minter_id, amount);
     }
 
     /// get stored metadata or default
@@ -40,20 +53,165 @@ impl Contract {
             .expect(format!("Account {} is not registered", account_id).as_str())
     }
 
-    pub(crate) fn mint(&mut self, account_id: &AccountId, amount: Balance) {
+    /// returns all vesting grants of the account, including a not yet converted legacy record
+    /// (with grant id 0).
+    pub(crate) fn internal_get_vestings(&self, account_id: &AccountId) -> Vec<VestingRecord> {
+        if let Some(v) = self.vested.get(account_id) {
+            return v;
+        }
+        match self.legacy_vested.get(account_id) {
+            Some(r) => vec![r.to_vesting_record(0)],
+            None => vec![],
+        }
+    }
+
+    /// stores vesting grants of the account and removes the legacy record.
+    pub(crate) fn internal_save_vestings(
+        &mut self,
+        account_id: &AccountId,
+        vestings: &[VestingRecord],
+    ) {
+        self.legacy_vested.remove(account_id);
+        if vestings.is_empty() {
+            self.vested.remove(account_id);
+        } else {
+            self.vested.insert(account_id, &vestings.to_vec());
+        }
+    }
+
+    /// Converts the legacy vesting record of the account (if any), assigning it a new
+    /// grant id.
+    pub(crate) fn internal_convert_legacy_vesting(&mut self, account_id: &AccountId) {
+        if let Some(r) = self.legacy_vested.get(account_id) {
+            let id = self.next_vesting_id;
+            self.next_vesting_id += 1;
+            self.internal_save_vestings(account_id, &[r.to_vesting_record(id)]);
+        }
+    }
+
+    /// Returns the amount locked across all vesting grants of the account.
+    /// Fully vested grants are removed and the aggregated vesting lock is updated.
+    /// A legacy vesting record is converted.
+    pub(crate) fn internal_update_locked(&mut self, account_id: &AccountId) -> Balance {
+        self.internal_convert_legacy_vesting(account_id);
+        let mut vestings = self.internal_get_vestings(account_id);
+        if vestings.is_empty() {
+            self.internal_record_vesting_locked(account_id, 0);
+            return 0;
+        }
+        let n = vestings.len();
+        let mut locked = 0;
+        vestings.retain(|v| {
+            let l = v.compute_amount_locked();
+            locked += l;
+            l > 0
+        });
+        if vestings.len() != n {
+            //some grants are complete. remove their vesting lock
+            self.internal_save_vestings(account_id, &vestings);
+        }
+        self.internal_record_vesting_locked(account_id, locked);
+        locked
+    }
+
+    /// Returns the amount locked across all vesting grants of the account.
+    pub(crate) fn internal_locked_amount(&self, account_id: &AccountId) -> Balance {
+        self.internal_get_vestings(account_id)
+            .iter()
+            .map(|v| v.compute_amount_locked())
+            .sum()
+    }
+
+    /// Panics if the account `balance` (after a balance reducing operation) is lower than
+    /// the amount locked in the account vesting grants.
+    /// All paths which reduce an account balance must call this function.
+    pub(crate) fn assert_vesting_lock(&mut self, account_id: &AccountId, balance: Balance) {
+        let locked = self.internal_update_locked(account_id);
+        assert!(
+            balance >= locked,
+            "Account with vesting, balance can't go lower than {}",
+            locked
+        );
+    }
+
+    /// Stores the account balance record, its balance checkpoint, moves delegated votes and
+    /// updates the holder index.
+    /// All token balance updates must go through this function.
+    pub(crate) fn internal_save_account(&mut self, account_id: &AccountId, ab: &AccBalance) {
+        let old_balance = self.accounts.insert(account_id, ab).map_or(0, |a| a.token);
+        self.record_balance_checkpoint(account_id, ab.token);
+        self.internal_refresh_delegated_votes(account_id);
+        self.internal_update_holders(account_id, old_balance, ab.token);
+    }
+
+    /// Mints new tokens. All mint paths must go through this function or `internal_mint`.
+    /// Panics if the max supply is exceeded.
+    pub(crate) fn mint(&mut self, account_id: &AccountId, amount: Balance, memo: Option<&str>) {
+        self.internal_mint(account_id, amount);
+        FtMint {
+            owner_id: account_id,
+            amount: amount.into(),
+            memo,
+        }
+        .emit();
+    }
+
+    /// Mints new tokens without emitting an event. The caller is responsible for emitting
+    /// the `ft_mint` event.
+    pub(crate) fn internal_mint(&mut self, account_id: &AccountId, amount: Balance) {
+        self.assert_not_paused(PauseFeature::Mint);
+        assert!(
+            amount <= self.max_supply - self.total_supply,
+            "max supply exceeded, mintable remaining: {}",
+            self.max_supply - self.total_supply
+        );
         let mut ab = self.try_register_account(account_id, 0);
         ab.token += amount;
-        self.accounts.insert(account_id, &ab);
+        self.internal_save_account(account_id, &ab);
         self.total_supply += amount;
+        self.total_minted += amount;
+        self.record_supply_checkpoint();
     }
 
-    pub(crate) fn internal_burn(&mut self, account_id: &AccountId, amount: u128) {
+    pub(crate) fn internal_burn(
+        &mut self,
+        account_id: &AccountId,
+        amount: u128,
+        memo: Option<&str>,
+    ) {
+        self.assert_not_frozen(account_id);
+        self.internal_force_burn(account_id, amount, memo);
+    }
+
+    /// Burns tokens like `internal_burn`, but also from a frozen account. Used only for
+    /// vesting reclaims initiated by vesting managers.
+    pub(crate) fn internal_force_burn(
+        &mut self,
+        account_id: &AccountId,
+        amount: u128,
+        memo: Option<&str>,
+    ) {
+        self.assert_not_paused(PauseFeature::Burn);
         assert!(amount > 0, "can't burn 0 tokens");
         let mut ab = self._must_balance_of(account_id);
+        assert!(
+            amount <= ab.token,
+            "The account doesn't have enough balance {}",
+            ab.token
+        );
         ab.token -= amount;
-        self.accounts.insert(account_id, &ab);
+        self.assert_vesting_lock(account_id, ab.token);
+        self.internal_save_account(account_id, &ab);
         assert!(self.total_supply >= amount);
         self.total_supply -= amount;
+        self.total_burned += amount;
+        self.record_supply_checkpoint();
+        FtBurn {
+            owner_id: account_id,
+            amount: amount.into(),
+            memo,
+        }
+        .emit();
     }
 
     pub(crate) fn internal_transfer(
@@ -62,12 +220,84 @@ impl Contract {
         receiver_id: &AccountId,
         amount: Balance,
         memo: Option<String>,
+    ) {
+        self.internal_transfer_with_fee(sender_id, receiver_id, amount, memo);
+    }
+
+    /// Transfers tokens, charging the transfer fee, and emits the `ft_transfer` event
+    /// (with an additional entry for the fee).
+    /// Returns the amount credited to the receiver and the charged fee.
+    pub(crate) fn internal_transfer_with_fee(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> (Balance, Balance) {
+        let (net, fee) = self.internal_move_balance_with_fee(sender_id, receiver_id, amount);
+        let mut events = vec![FtTransfer {
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            amount: net.into(),
+            memo: memo.as_deref(),
+        }];
+        if fee > 0 {
+            events.push(FtTransfer {
+                old_owner_id: sender_id,
+                new_owner_id: self.fee_treasury().unwrap(),
+                amount: fee.into(),
+                memo: Some(TRANSFER_FEE_MEMO),
+            });
+        }
+        FtTransfer::emit_many(&events);
+        (net, fee)
+    }
+
+    /// Transfers tokens without charging the transfer fee and emits the `ft_transfer` event.
+    /// Used for internal moves, like sweeping the balance of a closed account.
+    pub(crate) fn internal_transfer_without_fee(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: &str,
+    ) {
+        self.internal_move_balance(sender_id, receiver_id, amount);
+        FtTransfer {
+            old_owner_id: sender_id,
+            new_owner_id: receiver_id,
+            amount: amount.into(),
+            memo: Some(memo),
+        }
+        .emit();
+    }
+
+    /// Moves tokens between accounts without emitting an event. The caller is responsible
+    /// for emitting the `ft_transfer` event.
+    pub(crate) fn internal_move_balance(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.assert_not_frozen(sender_id);
+        self.internal_force_move_balance(sender_id, receiver_id, amount);
+    }
+
+    /// Moves tokens like `internal_move_balance`, but also from a frozen sender. Used only
+    /// for vesting reclaims initiated by vesting managers.
+    pub(crate) fn internal_force_move_balance(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
     ) {
         assert_ne!(
             sender_id, receiver_id,
             "Sender and receiver should be different"
         );
         assert!(amount > 0, "The amount should be a positive number");
+        self.assert_not_paused(PauseFeature::Transfer);
 
         // remove from sender
         let mut sender_balance = self._must_balance_of(sender_id);
@@ -77,39 +307,59 @@ impl Contract {
             sender_balance.token
         );
         sender_balance.token -= amount;
-        self.accounts.insert(sender_id, &sender_balance);
+        self.internal_save_account(sender_id, &sender_balance);
 
         // check vesting
-        match self.vested.get(&sender_id) {
-            Some(vesting) => {
-                //compute locked
-                let locked = vesting.compute_amount_locked();
-                if locked == 0 {
-                    //vesting is complete. remove vesting lock
-                    self.vested.remove(&sender_id);
-                } else {
-                    assert!(
-                        sender_balance.token >= locked,
-                        "Account with vesting, balance can't go lower than {}",
-                        locked
-                    );
-                }
-            }
-            None => {}
-        }
+        self.assert_vesting_lock(sender_id, sender_balance.token);
 
         // add to receiver
         let mut receiver_balance = self._must_balance_of(receiver_id);
         receiver_balance.token += amount;
-        self.accounts.insert(receiver_id, &receiver_balance);
-
-        log!(
-            "Transfer {} from {} to {}, memo: {}",
-            amount,
-            sender_id,
-            receiver_id,
-            memo.unwrap_or_default()
+        self.internal_save_account(receiver_id, &receiver_balance);
+    }
+
+    /// Transfers tokens to the `receiver_id` and calls `ft_on_transfer` on the receiver
+    /// followed by the `ft_resolve_transfer` callback, which refunds unused tokens
+    /// back to the `sender_id`. The receiver is notified about the amount it received
+    /// (after the transfer fee), so refunds never exceed it. The fee is not refunded.
+    pub(crate) fn internal_transfer_call(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        memo: Option<String>,
+        msg: String,
+    ) -> Promise {
+        let (amount, fee) = self.internal_transfer_with_fee(&sender_id, &receiver_id, amount, memo);
+        // Initiating receiver's call and the callback
+        let on_transfer = ext_ft_receiver::ft_on_transfer(
+            sender_id.clone(),
+            amount.into(),
+            msg,
+            &receiver_id,
+            NO_DEPOSIT,
+            env::prepaid_gas() - GAS_FOR_FT_TRANSFER_CALL,
         );
+        if fee == 0 {
+            on_transfer.then(ext_self::ft_resolve_transfer(
+                sender_id,
+                receiver_id,
+                amount.into(),
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+        } else {
+            on_transfer.then(ext_self::ft_resolve_transfer_with_fee(
+                sender_id,
+                receiver_id,
+                amount.into(),
+                fee.into(),
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+        }
     }
 
     /// Helper method to update balance of the sender and receiver based on the return
@@ -142,37 +392,55 @@ impl Contract {
             PromiseResult::Failed => amount,
         };
 
+        if unused_amount > 0 && self.internal_is_frozen(&receiver_id) {
+            log!(
+                "Receiver {} is frozen, can't refund {} tokens to the sender",
+                &receiver_id,
+                unused_amount
+            );
+            return (amount, 0);
+        }
+
         if unused_amount > 0 {
             let mut receiver_balance = self._must_balance_of(&receiver_id);
-            // receiver has positive balance, so we can refund.
-            if receiver_balance.token > 0 {
-                // adjust the refund amount to the receiver balance
-                unused_amount = std::cmp::min(receiver_balance.token, unused_amount);
+            // tokens locked in the receiver vesting grants can't be refunded.
+            let spendable = receiver_balance
+                .token
+                .saturating_sub(self.internal_update_locked(&receiver_id));
+            // receiver has positive spendable balance, so we can refund.
+            if spendable > 0 {
+                // adjust the refund amount to the receiver spendable balance
+                unused_amount = std::cmp::min(spendable, unused_amount);
                 receiver_balance.token -= unused_amount;
-                self.accounts.insert(&receiver_id, &receiver_balance);
+                self.internal_save_account(&receiver_id, &receiver_balance);
 
                 // now we will try to update sender balance
                 if let Some(mut sender_balance) = self.accounts.get(sender_id) {
                     sender_balance.token += unused_amount;
-                    self.accounts.insert(sender_id, &sender_balance);
-                    log!(
-                        "Refund {} from {} to {}",
-                        unused_amount,
-                        receiver_id,
-                        sender_id
-                    );
+                    self.internal_save_account(sender_id, &sender_balance);
+                    FtTransfer {
+                        old_owner_id: &receiver_id,
+                        new_owner_id: sender_id,
+                        amount: unused_amount.into(),
+                        memo: Some("refund"),
+                    }
+                    .emit();
                     return (amount - unused_amount, 0);
                 } else {
                     // Sender's account was deleted, so we need to burn tokens.
                     self.total_supply -= unused_amount;
-                    log!(
-                        "The sender account is deleted, can't make a refund. Burning {} from ft_transfer_call",
-                        unused_amount
-                    );
+                    self.total_burned += unused_amount;
+                    self.record_supply_checkpoint();
+                    FtBurn {
+                        owner_id: &receiver_id,
+                        amount: unused_amount.into(),
+                        memo: Some("refund to a deleted sender account"),
+                    }
+                    .emit();
                     return (amount - unused_amount, unused_amount);
                 }
             } else {
-                log!("Reciever {} didn't use all tokens, but it's balance is 0 so can't refund {} tokens to the sender",
+                log!("Reciever {} didn't use all tokens, but it's spendable balance is 0 so can't refund {} tokens to the sender",
                      &receiver_id, unused_amount);
             }
         }
@@ -4,9 +4,16 @@
 /// - No account storage complexity - Since NEAR slashed storage price by 10x
 /// it does not make sense to add that friction (storage backup per user).
 /// Token creator must store enough NEAR in the contract to support growth.
+/// - Role based access control: admin, minter, vesting manager, metadata manager, pauser
+/// and upgrader roles. The owner is always an admin.
+/// - Pausable: guardians (accounts with the pauser role, distinct from the owner) can pause
+/// the whole token or separately minting, transfers and burning.
 /// - Multi-minters, no fixed total_supply:
-/// The owner can add/remove allowed minters. This is useful if you want
-/// an external contract, a farm for example, to be able to mint tokens
+/// Admins can add/remove allowed minters. This is useful if you want
+/// an external contract, a farm for example, to be able to mint tokens.
+/// Each minter has a total mint allowance and an optional per period emission cap.
+/// - Hard max supply: total_supply can never exceed `max_supply`. The owner can only
+/// lower `max_supply`.
 /// - Ultra-Lazy ft-metadata: ft-metadata is not stored unless changed
 ///
 use near_contract_standards::fungible_token::{
@@ -15,29 +22,58 @@ use near_contract_standards::fungible_token::{
     resolver::FungibleTokenResolver,
 };
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::{ValidAccountId, U128};
 use near_sdk::{
-    assert_one_yocto, env, ext_contract, log, near_bindgen, AccountId, Balance, Gas,
-    PanicOnDefault, PromiseOrValue,
+    assert_one_yocto, env, ext_contract, log, near_bindgen, AccountId, Balance, CryptoHash, Gas,
+    PanicOnDefault, PromiseOrValue, StorageUsage,
 };
 
 const TGAS: Gas = 1_000_000_000_000;
-const GAS_FOR_RESOLVE_TRANSFER: Gas = 5 * TGAS;
+/// `ft_resolve_transfer` also updates the balance and vote histories, delegated votes and
+/// the holder index of both accounts (see `test_resolve_transfer_gas`).
+const GAS_FOR_RESOLVE_TRANSFER: Gas = 20 * TGAS;
 const GAS_FOR_FT_TRANSFER_CALL: Gas = 25 * TGAS + GAS_FOR_RESOLVE_TRANSFER;
 const NO_DEPOSIT: Balance = 0;
 
 near_sdk::setup_alloc!();
 
+mod airdrop;
+mod allowance;
+mod batch;
+mod bridge;
+mod checkpoints;
+mod delegation;
+mod events;
+mod fee;
+mod freeze;
+mod holders;
 mod internal;
 mod migrations;
+mod minters;
+mod ownership;
+mod pause;
+mod roles;
 mod storage;
+mod supply;
 mod upgrade;
 mod util;
 mod vesting;
 
+use airdrop::AirdropCampaign;
+use allowance::Allowance;
+use checkpoints::{History, SUPPLY_HISTORY_CAPACITY};
+use delegation::Delegation;
+use events::{FtTransfer, VestingCancelled};
+use fee::TransferFee;
+use migrations::OldVestingRecord;
+use minters::{MinterAllowance, MinterAllowanceJSON};
+use ownership::PendingOwner;
+use pause::{PauseFeature, PauseStatus};
+use roles::Role;
+use upgrade::StagedUpgrade;
 use util::*;
-use vesting::{VestingRecord, VestingRecordJSON};
+use vesting::{VestingParams, VestingRecord, VestingRecordJSON, VestingSchedule};
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -46,16 +82,90 @@ pub struct Contract {
 
     pub accounts: LookupMap<AccountId, storage::AccBalance>,
     pub owner_id: AccountId,
-    pub minters: Vec<AccountId>,
+    /// owner proposed with `propose_owner`, waiting for acceptance.
+    pub pending_owner: Option<PendingOwner>,
+    /// role -> accounts with the role
+    pub roles: LookupMap<Role, Vec<AccountId>>,
     pub total_supply: Balance,
-    pub vested: LookupMap<AccountId, VestingRecord>,
+    /// vesting records created before multiple grants per account were supported.
+    pub legacy_vested: LookupMap<AccountId, OldVestingRecord>,
+    /// vesting grants of each account.
+    pub vested: LookupMap<AccountId, Vec<VestingRecord>>,
+    /// id of the next vesting grant.
+    pub next_vesting_id: u64,
+    /// mint limits of each minter.
+    pub minter_allowances: LookupMap<AccountId, MinterAllowance>,
+    /// spending allowances: (owner, spender) -> allowance
+    pub allowances: LookupMap<(AccountId, AccountId), Allowance>,
+    /// measured storage usage of a single allowance.
+    pub allowance_storage_usage: StorageUsage,
+    /// hard cap for the total_supply. Can be lowered, but never raised.
+    pub max_supply: Balance,
+    /// paused features, controlled by guardians.
+    pub paused: PauseStatus,
+    /// historical balances of accounts which enabled the balance history.
+    pub balance_checkpoints: LookupMap<AccountId, History>,
+    /// historical total supply.
+    pub supply_checkpoints: History,
+    /// measured storage usage of an empty account history.
+    pub history_storage_usage: StorageUsage,
+    /// measured storage usage of a single checkpoint.
+    pub checkpoint_storage_usage: StorageUsage,
+    /// delegator -> delegation.
+    pub delegates: LookupMap<AccountId, Delegation>,
+    /// current voting power of each delegatee.
+    pub votes: LookupMap<AccountId, Balance>,
+    /// historical voting power of delegatees which enabled the vote history.
+    pub vote_checkpoints: LookupMap<AccountId, History>,
+    pub airdrops: LookupMap<u64, AirdropCampaign>,
+    /// claimed airdrop leaves: (campaign id, leaf hash).
+    pub airdrop_claims: LookupSet<(u64, CryptoHash)>,
+    pub next_airdrop_id: u64,
+    /// measured storage usage of a single account registration.
+    pub account_storage_usage: StorageUsage,
+    /// NEAR available to pay for sponsored (free) account registrations.
+    pub sponsor_pool: Balance,
+    /// storage bytes used by sponsored accounts.
+    pub sponsored_storage: StorageUsage,
+    /// NEAR paid by the sponsor pool for each sponsored account, returned to the pool when
+    /// the account is removed.
+    pub sponsored_accounts: LookupMap<AccountId, Balance>,
+    /// storage bytes used by accounts registered with a storage deposit.
+    pub user_paid_storage: StorageUsage,
+    /// upgrade waiting for the timelock.
+    pub staged_upgrade: Option<StagedUpgrade>,
+    /// mint limits of each bridge minter.
+    pub bridge_minter_allowances: LookupMap<AccountId, MinterAllowance>,
+    pub next_bridge_nonce: u64,
+    /// processed bridge deposits: sha256 of the borsh serialized (source chain, deposit id).
+    pub bridge_deposits: LookupSet<Vec<u8>>,
+    /// accounts with a positive balance.
+    pub holders: UnorderedSet<AccountId>,
+    /// false until all holders from before the holder index was introduced are indexed.
+    pub holders_backfill_complete: bool,
+    pub total_minted: Balance,
+    pub total_burned: Balance,
+    /// last recorded vesting lock of each account with vesting.
+    pub vesting_locked: LookupMap<AccountId, Balance>,
+    /// sum of the recorded vesting locks of not excluded accounts.
+    pub total_vesting_locked: Balance,
+    /// amount minted by each minter and bridge minter.
+    pub minted_by_minter: UnorderedMap<AccountId, Balance>,
+    /// accounts (eg treasury) excluded from the circulating supply.
+    pub excluded_accounts: UnorderedSet<AccountId>,
+    /// frozen accounts: account -> timestamp until which the account is frozen.
+    pub frozen: LookupMap<AccountId, u64>,
+    pub transfer_fee: Option<TransferFee>,
+    /// accounts exempted from the transfer fee.
+    pub fee_exempt: UnorderedSet<AccountId>,
 }
 
 #[near_bindgen]
 impl Contract {
     /// Initializes the contract with the given total supply owned by the given `owner_id`.
+    /// `max_supply` is the maximum amount of tokens which can ever be minted.
     #[init]
-    pub fn new(owner_id: AccountId) -> Self {
+    pub fn new(owner_id: AccountId, max_supply: U128String) -> Self {
         let m = FungibleTokenMetadata {
             spec: FT_METADATA_SPEC.to_string(),
             name: "Cheddar".to_string(),
@@ -69,14 +179,68 @@ impl Contract {
         };
         m.assert_valid();
 
-        Self {
+        let mut this = Self {
             owner_id: owner_id.clone(),
+            pending_owner: None,
             metadata: LazyOption::new(b"m".to_vec(), Some(&m)),
             accounts: LookupMap::new(b"a".to_vec()),
-            minters: vec![owner_id],
+            roles: LookupMap::new(b"r".to_vec()),
             total_supply: 0,
-            vested: LookupMap::new(b"v".to_vec()),
+            legacy_vested: LookupMap::new(b"v".to_vec()),
+            vested: LookupMap::new(b"w".to_vec()),
+            next_vesting_id: 1,
+            minter_allowances: LookupMap::new(b"l".to_vec()),
+            allowances: LookupMap::new(b"p".to_vec()),
+            allowance_storage_usage: 0,
+            max_supply: max_supply.0,
+            paused: PauseStatus::default(),
+            balance_checkpoints: LookupMap::new(b"c".to_vec()),
+            supply_checkpoints: History::new(b"s".to_vec(), SUPPLY_HISTORY_CAPACITY, 0),
+            history_storage_usage: 0,
+            checkpoint_storage_usage: 0,
+            delegates: LookupMap::new(b"d".to_vec()),
+            votes: LookupMap::new(b"b".to_vec()),
+            vote_checkpoints: LookupMap::new(b"o".to_vec()),
+            airdrops: LookupMap::new(b"e".to_vec()),
+            airdrop_claims: LookupSet::new(b"f".to_vec()),
+            next_airdrop_id: 1,
+            account_storage_usage: 0,
+            sponsor_pool: 0,
+            sponsored_storage: 0,
+            sponsored_accounts: LookupMap::new(b"n".to_vec()),
+            user_paid_storage: 0,
+            staged_upgrade: None,
+            bridge_minter_allowances: LookupMap::new(b"q".to_vec()),
+            next_bridge_nonce: 1,
+            bridge_deposits: LookupSet::new(b"h".to_vec()),
+            holders: UnorderedSet::new(b"i".to_vec()),
+            holders_backfill_complete: true,
+            total_minted: 0,
+            total_burned: 0,
+            vesting_locked: LookupMap::new(b"k".to_vec()),
+            total_vesting_locked: 0,
+            minted_by_minter: UnorderedMap::new(b"t".to_vec()),
+            excluded_accounts: UnorderedSet::new(b"j".to_vec()),
+            frozen: LookupMap::new(b"z".to_vec()),
+            transfer_fee: None,
+            fee_exempt: UnorderedSet::new(b"x".to_vec()),
+        };
+        this.measure_storage_usage();
+        this.record_supply_checkpoint();
+        migrations::write_state_version(migrations::STATE_VERSION);
+        for role in [
+            Role::Minter,
+            Role::VestingManager,
+            Role::MetadataManager,
+            Role::Upgrader,
+        ]
+        .iter()
+        {
+            this.internal_grant_role(*role, &owner_id);
         }
+        this.minter_allowances
+            .insert(&owner_id, &MinterAllowance::new(u128::MAX));
+        this
     }
 
     /// Returns account ID of the owner.
@@ -85,67 +249,93 @@ impl Contract {
     }
 
     /// Mints new tokens to the `account_id`.
-    /// Panics if the function is calle by a not registered minter.
+    /// Panics if the function is calle by a not registered minter or if the minter
+    /// allowance or period cap is exceeded.
     #[payable]
     pub fn ft_mint(&mut self, receiver_id: &AccountId, amount: U128String, memo: Option<String>) {
         assert_one_yocto();
-        log!(
-            "Minting {} CHEDDAR to {}, memo: {}",
-            amount.0,
-            receiver_id,
-            if let Some(m) = memo {
-                m
-            } else {
-                "".to_string()
-            }
-        );
-        self.assert_minter(env::predecessor_account_id());
-        self.mint(receiver_id, amount.0);
+        let minter_id = env::predecessor_account_id();
+        self.assert_role(Role::Minter);
+        self.consume_minter_allowance(&minter_id, amount.0);
+        self.mint(receiver_id, amount.0, memo.as_deref());
     }
 
     /// burns `amount` from own supply of coins
     #[payable]
     pub fn self_burn(&mut self, amount: U128String) {
         assert_one_yocto();
-        self.internal_burn(&env::predecessor_account_id(), amount.0);
+        self.internal_burn(&env::predecessor_account_id(), amount.0, None);
     }
 
     //-----------
     //-- Admin
     //-----------
 
-    /// owner can add/remove minters
+    /// admins can add/remove minters. `allowance` is the total amount the new minter
+    /// is allowed to mint.
     #[payable]
-    pub fn add_minter(&mut self, account_id: AccountId) {
+    pub fn add_minter(&mut self, account_id: AccountId, allowance: U128String) {
         assert_one_yocto();
-        self.assert_owner();
-        if let Some(_) = self.minters.iter().position(|x| *x == account_id) {
-            //found
-            panic!("already in the list");
-        }
-        self.minters.push(account_id);
+        self.assert_role(Role::Admin);
+        assert!(
+            self.internal_grant_role(Role::Minter, &account_id),
+            "already in the list"
+        );
+        self.minter_allowances
+            .insert(&account_id, &MinterAllowance::new(allowance.0));
     }
 
     #[payable]
     pub fn remove_minter(&mut self, account_id: &AccountId) {
         assert_one_yocto();
-        self.assert_owner();
-        if let Some(inx) = self.minters.iter().position(|x| x == account_id) {
-            //found
-            let _removed = self.minters.swap_remove(inx);
-        } else {
-            panic!("not a minter")
-        }
+        self.assert_role(Role::Admin);
+        assert!(
+            self.internal_revoke_role(Role::Minter, account_id),
+            "not a minter"
+        );
+    }
+
+    pub fn get_minters(&self) -> Vec<AccountId> {
+        self.get_role_members(Role::Minter)
+    }
+
+    /// Sets the remaining total amount the minter is allowed to mint.
+    /// Use it to raise or lower the minter allowance.
+    #[payable]
+    pub fn set_minter_allowance(&mut self, account_id: AccountId, allowance: U128String) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        let mut a = self.internal_get_minter_allowance(&account_id);
+        a.allowance = allowance.0;
+        self.minter_allowances.insert(&account_id, &a);
+    }
+
+    /// Sets the maximum amount the minter can mint within a single period of
+    /// `period_duration` nanoseconds (eg 1 day). Removes the cap if `period_cap` is None.
+    #[payable]
+    pub fn set_minter_period_cap(
+        &mut self,
+        account_id: AccountId,
+        period_cap: Option<U128String>,
+        period_duration: U64String,
+    ) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        let mut a = self.internal_get_minter_allowance(&account_id);
+        a.set_period_cap(period_cap.map(|c| c.0), period_duration.0);
+        self.minter_allowances.insert(&account_id, &a);
     }
 
-    pub fn get_minters(self) -> Vec<AccountId> {
-        self.minters
+    /// Returns the minter allowance: remaining amount, amount minted so far and the
+    /// period cap. Returns None if the account is not a minter.
+    pub fn get_minter_allowance(&self, account_id: AccountId) -> Option<MinterAllowanceJSON> {
+        self.minter_allowances.get(&account_id).map(|a| a.to_json())
     }
 
     #[payable]
     pub fn set_metadata_icon(&mut self, svg_string: String) {
         assert_one_yocto();
-        self.assert_owner();
+        self.assert_role(Role::MetadataManager);
         let mut m = self.internal_get_ft_metadata();
         m.icon = Some(svg_string);
         self.metadata.set(&m);
@@ -154,7 +344,7 @@ impl Contract {
     #[payable]
     pub fn set_metadata_reference(&mut self, reference: String, reference_hash: String) {
         assert_one_yocto();
-        self.assert_owner();
+        self.assert_role(Role::MetadataManager);
         let mut m = self.internal_get_ft_metadata();
         m.reference = Some(reference);
         m.reference_hash = Some(reference_hash.as_bytes().to_vec().into());
@@ -162,75 +352,182 @@ impl Contract {
         self.metadata.set(&m);
     }
 
-    pub fn set_owner(&mut self, owner_id: ValidAccountId) {
-        self.assert_owner();
-        self.owner_id = owner_id.as_ref().clone();
-    }
-
     /// Get the owner of this account.
     pub fn get_owner(&self) -> AccountId {
         self.owner_id.clone()
     }
 
+    /// Lowers the max supply. Panics if `max_supply` is bigger than the current max supply
+    /// or smaller than the current total supply.
+    #[payable]
+    pub fn set_max_supply(&mut self, max_supply: U128String) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            max_supply.0 <= self.max_supply,
+            "max supply can't be raised, current max supply: {}",
+            self.max_supply
+        );
+        assert!(
+            max_supply.0 >= self.total_supply,
+            "max supply can't be lower than the total supply: {}",
+            self.total_supply
+        );
+        self.max_supply = max_supply.0;
+    }
+
+    //-----------
+    //-- Supply
+    //-----------
+
+    /// Returns the maximum amount of tokens which can ever exist.
+    pub fn ft_max_supply(&self) -> U128String {
+        self.max_supply.into()
+    }
+
+    /// Returns the amount of tokens which can still be minted before reaching the max supply.
+    pub fn ft_mintable_remaining(&self) -> U128String {
+        (self.max_supply - self.total_supply).into()
+    }
+
     //-----------
     //-- Vesting
     //-----------
 
     /// Get the amount of tokens that are locked in this account due to lockup or vesting.
+    /// The locked amount is summed over all vesting grants of the account.
     pub fn get_locked_amount(&self, account: AccountId) -> U128String {
-        match self.vested.get(&account) {
-            Some(vesting) => vesting.compute_amount_locked().into(),
-            None => 0.into(),
-        }
+        self.internal_locked_amount(&account).into()
     }
 
-    /// Get vesting information
-    pub fn get_vesting_info(&self, account_id: AccountId) -> VestingRecordJSON {
-        log!("{}", &account_id);
-        let vesting = self.vested.get(&account_id).unwrap();
-        VestingRecordJSON {
-            amount: vesting.amount.into(),
-            cliff_timestamp: vesting.cliff_timestamp.into(),
-            end_timestamp: vesting.end_timestamp.into(),
-        }
+    /// Returns the part of the account balance which is locked due to vesting.
+    pub fn ft_locked_balance_of(&self, account_id: ValidAccountId) -> U128String {
+        let balance = self._balance_of(account_id.as_ref());
+        std::cmp::min(balance, self.internal_locked_amount(account_id.as_ref())).into()
+    }
+
+    /// Returns the part of the account balance which can be transferred or burned.
+    pub fn ft_spendable_balance_of(&self, account_id: ValidAccountId) -> U128String {
+        let balance = self._balance_of(account_id.as_ref());
+        balance
+            .saturating_sub(self.internal_locked_amount(account_id.as_ref()))
+            .into()
     }
 
-    /// minters can mint with vesting/locked periods
+    /// Get information about all vesting grants of the account.
+    /// Returns an empty list if the account is not vested.
+    pub fn get_vesting_info(&self, account_id: AccountId) -> Vec<VestingRecordJSON> {
+        self.internal_get_vestings(&account_id)
+            .iter()
+            .map(|v| v.to_json())
+            .collect()
+    }
+
+    /// Get information about a single vesting grant of the account.
+    pub fn get_vesting_grant(
+        &self,
+        account_id: AccountId,
+        grant_id: u64,
+    ) -> Option<VestingRecordJSON> {
+        self.internal_get_vestings(&account_id)
+            .iter()
+            .find(|v| v.id == grant_id)
+            .map(|v| v.to_json())
+    }
+
+    /// minters can mint with vesting/locked periods.
+    /// Each call creates a new vesting grant, an account can have multiple grants.
+    /// See `VestingParams` for the schedule parameters.
+    /// Returns the grant id.
     /// NOTE: we don't charge storage fees for vesting accounts.
     #[payable]
     pub fn mint_vested(
         &mut self,
         receiver_id: &AccountId,
         amount: U128String,
-        cliff_timestamp: U64String,
-        end_timestamp: U64String,
-    ) {
+        params: VestingParams,
+    ) -> u64 {
         self.ft_mint(receiver_id, amount, Some("vesting".to_string()));
-        let record =
-            VestingRecord::new(amount.into(), cliff_timestamp.into(), end_timestamp.into());
-        match self.vested.insert(&receiver_id, &record) {
-            Some(_) => panic!("account already vested"),
-            None => {}
-        }
+        self.internal_convert_legacy_vesting(receiver_id);
+        let id = self.next_vesting_id;
+        self.next_vesting_id += 1;
+        let schedule = match params.period {
+            Some(p) => VestingSchedule::Periodic { period: p.0 },
+            None => VestingSchedule::Linear,
+        };
+        let mut record = VestingRecord::new(
+            id,
+            amount.into(),
+            params
+                .start_timestamp
+                .unwrap_or(params.cliff_timestamp)
+                .into(),
+            params.cliff_timestamp.into(),
+            params.end_timestamp.into(),
+            schedule,
+            params.immediate_unlock_bps.unwrap_or(0),
+        );
+        record.revocable = params.revocable.unwrap_or(true);
+        let mut vestings = self.internal_get_vestings(receiver_id);
+        vestings.push(record);
+        self.internal_save_vestings(receiver_id, &vestings);
+        self.internal_update_locked(receiver_id);
+        id
     }
 
-    /// Cancels token allocation in a vesting account. All not vested tokens
-    /// will be burned.
-    /// Only owner can call this function.
+    /// Cancels token allocation in a vesting grant. Only the still locked tokens are
+    /// reclaimed: they are transferred to `treasury_id` or burned if `treasury_id` is not
+    /// provided. Already vested tokens stay in the account.
+    /// Panics if the grant is irrevocable. Works also for frozen accounts.
+    /// Returns the amount of reclaimed tokens.
+    /// Only vesting managers can call this function.
     #[payable]
-    pub fn cancel_vesting(&mut self, account_id: &AccountId) {
+    pub fn cancel_vesting(
+        &mut self,
+        account_id: &AccountId,
+        grant_id: u64,
+        treasury_id: Option<ValidAccountId>,
+    ) -> U128String {
         assert_one_yocto();
-        self.assert_owner();
-        match self.vested.get(&account_id) {
-            Some(vesting) => {
-                if vesting.compute_amount_locked() == 0 {
-                    panic!("past the cliff, vesting can't be changed")
+        self.assert_role(Role::VestingManager);
+        let mut vestings = self.internal_get_vestings(account_id);
+        let vesting = match vestings.iter().position(|v| v.id == grant_id) {
+            Some(inx) => vestings.remove(inx),
+            None => panic!("vesting grant not found"),
+        };
+        assert!(vesting.revocable, "vesting grant is irrevocable");
+        self.internal_save_vestings(account_id, &vestings);
+        self.internal_update_locked(account_id);
+
+        let reclaimed = std::cmp::min(
+            vesting.compute_amount_locked(),
+            self._balance_of(account_id),
+        );
+        let treasury_id: Option<AccountId> = treasury_id.map(|t| t.into());
+        // reclaims are allowed from frozen accounts
+        if reclaimed > 0 {
+            match &treasury_id {
+                Some(t) => {
+                    self.internal_force_move_balance(account_id, t, reclaimed);
+                    FtTransfer {
+                        old_owner_id: account_id,
+                        new_owner_id: t,
+                        amount: reclaimed.into(),
+                        memo: Some("vesting cancelled"),
+                    }
+                    .emit();
                 }
-                self.internal_burn(account_id, vesting.amount);
-                self.vested.remove(&account_id);
+                None => self.internal_force_burn(account_id, reclaimed, None),
             }
-            None => panic!("account not vested"),
         }
+        VestingCancelled {
+            account_id,
+            grant_id,
+            reclaimed: reclaimed.into(),
+            treasury_id: treasury_id.as_ref(),
+        }
+        .emit();
+        reclaimed.into()
     }
 }
 
@@ -255,26 +552,8 @@ impl FungibleTokenCore for Contract {
         assert_one_yocto();
         let sender_id = env::predecessor_account_id();
         let amount: Balance = amount.into();
-        self.internal_transfer(&sender_id, receiver_id.as_ref(), amount, memo);
-        // Initiating receiver's call and the callback
-        // ext_fungible_token_receiver::ft_on_transfer(
-        ext_ft_receiver::ft_on_transfer(
-            sender_id.clone(),
-            amount.into(),
-            msg,
-            receiver_id.as_ref(),
-            NO_DEPOSIT,
-            env::prepaid_gas() - GAS_FOR_FT_TRANSFER_CALL,
-        )
-        .then(ext_self::ft_resolve_transfer(
-            sender_id,
-            receiver_id.into(),
-            amount.into(),
-            &env::current_account_id(),
-            NO_DEPOSIT,
-            GAS_FOR_RESOLVE_TRANSFER,
-        ))
-        .into()
+        self.internal_transfer_call(sender_id, receiver_id.into(), amount, memo, msg)
+            .into()
     }
 
     fn ft_total_supply(&self) -> U128 {
@@ -308,6 +587,24 @@ impl FungibleTokenResolver for Contract {
     }
 }
 
+#[near_bindgen]
+impl Contract {
+    /// Callback of `ft_transfer_call` which charged a transfer fee. `amount` is the amount
+    /// credited to the receiver. Returns the used amount including the fee (the fee is
+    /// not refunded).
+    #[private]
+    pub fn ft_resolve_transfer_with_fee(
+        &mut self,
+        sender_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        fee: U128,
+    ) -> U128 {
+        let used = self.ft_resolve_transfer(sender_id, receiver_id, amount);
+        (used.0 + fee.0).into()
+    }
+}
+
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
     fn ft_metadata(&self) -> FungibleTokenMetadata {
@@ -333,17 +630,37 @@ trait FungibleTokenResolver {
         receiver_id: AccountId,
         amount: U128,
     ) -> U128;
+
+    fn ft_resolve_transfer_with_fee(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        fee: U128,
+    ) -> U128;
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, Balance};
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, Balance, PromiseResult};
 
     use super::*;
 
     const OWNER_SUPPLY: Balance = 1_000_000_000_000_000_000_000_000_000_000;
 
+    fn vesting_params(cliff_timestamp: u64, end_timestamp: u64) -> VestingParams {
+        VestingParams {
+            cliff_timestamp: cliff_timestamp.into(),
+            end_timestamp: end_timestamp.into(),
+            start_timestamp: None,
+            period: None,
+            immediate_unlock_bps: None,
+            revocable: None,
+        }
+    }
+
     fn get_context(predecessor_account_id: ValidAccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
         builder
@@ -357,13 +674,13 @@ mod tests {
     fn test_new() {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let mut contract = Contract::new(accounts(1).into());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
 
         testing_env!(context
             .attached_deposit(1)
             .predecessor_account_id(accounts(1))
             .build());
-        contract.mint(&accounts(1).to_string(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).to_string(), OWNER_SUPPLY.into(), None);
 
         testing_env!(context.is_view(true).build());
         assert_eq!(contract.ft_total_supply().0, OWNER_SUPPLY);
@@ -382,13 +699,13 @@ mod tests {
     fn test_transfer() {
         let mut context = get_context(accounts(2));
         testing_env!(context.build());
-        let mut contract = Contract::new(accounts(2).into());
+        let mut contract = Contract::new(accounts(2).into(), OWNER_SUPPLY.into());
 
         testing_env!(context
             .attached_deposit(1)
             .predecessor_account_id(accounts(2))
             .build());
-        contract.mint(&accounts(2).to_string(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(2).to_string(), OWNER_SUPPLY.into(), None);
 
         testing_env!(context
             .storage_usage(env::storage_usage())
@@ -416,4 +733,1622 @@ mod tests {
         );
         assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
     }
+
+    #[test]
+    fn test_minter_allowance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.add_minter(accounts(2).into(), 100.into());
+        contract.set_minter_period_cap(accounts(2).into(), Some(60.into()), 1000.into());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_mint(&accounts(3).into(), 50.into(), None);
+        let a = contract.get_minter_allowance(accounts(2).into()).unwrap();
+        assert_eq!(a.allowance.0, 50);
+        assert_eq!(a.minted.0, 50);
+        assert_eq!(a.mintable_now.0, 10);
+
+        // new period
+        testing_env!(context.block_timestamp(1000).build());
+        contract.ft_mint(&accounts(3).into(), 50.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+        let a = contract.get_minter_allowance(accounts(2).into()).unwrap();
+        assert_eq!(a.allowance.0, 0);
+        assert_eq!(a.mintable_now.0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "minter period cap exceeded")]
+    fn test_minter_period_cap() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.add_minter(accounts(2).into(), 100.into());
+        contract.set_minter_period_cap(accounts(2).into(), Some(60.into()), 1000.into());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_mint(&accounts(3).into(), 50.into(), None);
+        contract.ft_mint(&accounts(3).into(), 11.into(), None);
+    }
+
+    #[test]
+    fn test_max_supply() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), 100.into());
+        contract.ft_mint(&accounts(2).into(), 40.into(), None);
+        assert_eq!(contract.ft_mintable_remaining().0, 60);
+
+        contract.set_max_supply(50.into());
+        assert_eq!(contract.ft_max_supply().0, 50);
+        assert_eq!(contract.ft_mintable_remaining().0, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "max supply exceeded")]
+    fn test_mint_above_max_supply() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), 100.into());
+        contract.ft_mint(&accounts(2).into(), 60.into(), None);
+        contract.ft_mint(&accounts(2).into(), 41.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "max supply can't be raised")]
+    fn test_raise_max_supply() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), 100.into());
+        contract.set_max_supply(101.into());
+    }
+
+    #[test]
+    fn test_events() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        // skip the role events emitted by `new`
+        testing_env!(context.build());
+        contract.ft_mint(&accounts(1).into(), 100.into(), Some("airdrop".to_string()));
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"bob","amount":"100","memo":"airdrop"}]}"#
+            ]
+        );
+
+        testing_env!(context.build());
+        contract.mint(&accounts(2).into(), 1, None);
+        contract.ft_transfer(accounts(2), 10.into(), None);
+        assert_eq!(
+            get_logs()[1],
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","amount":"10"}]}"#
+        );
+
+        testing_env!(context.build());
+        contract.self_burn(5.into());
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"bob","amount":"5"}]}"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_vesting_grants() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1, None);
+        assert_eq!(contract.get_vesting_info(accounts(2).into()).len(), 0);
+        let g1 = contract.mint_vested(&accounts(2).into(), 100.into(), vesting_params(10, 20));
+        let g2 = contract.mint_vested(&accounts(2).into(), 50.into(), vesting_params(30, 40));
+        assert_ne!(g1, g2);
+        assert_eq!(contract.get_vesting_info(accounts(2).into()).len(), 2);
+        assert_eq!(contract.get_locked_amount(accounts(2).into()).0, 150);
+        assert_eq!(
+            contract
+                .get_vesting_grant(accounts(2).into(), g2)
+                .unwrap()
+                .amount
+                .0,
+            50
+        );
+
+        // first grant fully vested, second one still locked
+        testing_env!(context
+            .block_timestamp(25)
+            .predecessor_account_id(accounts(2))
+            .build());
+        assert_eq!(contract.get_locked_amount(accounts(2).into()).0, 50);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.get_vesting_info(accounts(2).into()).len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account with vesting, balance can't go lower than 150")]
+    fn test_vesting_lock_sums_grants() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint_vested(&accounts(2).into(), 100.into(), vesting_params(10, 20));
+        contract.mint_vested(&accounts(2).into(), 50.into(), vesting_params(30, 40));
+        contract.ft_mint(&accounts(2).into(), 10.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(1), 11.into(), None);
+    }
+
+    #[test]
+    fn test_vesting_schedules() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        // linear from 0 to 100, cliff at 50, 10% released immediately
+        contract.mint_vested(
+            &accounts(2).into(),
+            1000.into(),
+            VestingParams {
+                start_timestamp: Some(0.into()),
+                immediate_unlock_bps: Some(1000),
+                ..vesting_params(50, 100)
+            },
+        );
+        // 4 tranches: at 10, 20, 30 and 35 (end)
+        contract.mint_vested(
+            &accounts(3).into(),
+            1000.into(),
+            VestingParams {
+                period: Some(10.into()),
+                ..vesting_params(0, 35)
+            },
+        );
+        assert_eq!(contract.get_locked_amount(accounts(2).into()).0, 900);
+        assert_eq!(contract.get_locked_amount(accounts(3).into()).0, 1000);
+        let g = contract.get_vesting_info(accounts(3).into()).remove(0);
+        assert_eq!(g.period.unwrap().0, 10);
+
+        testing_env!(context.block_timestamp(25).build());
+        assert_eq!(contract.get_locked_amount(accounts(3).into()).0, 500);
+
+        testing_env!(context.block_timestamp(49).build());
+        assert_eq!(contract.get_locked_amount(accounts(2).into()).0, 900);
+        assert_eq!(contract.get_locked_amount(accounts(3).into()).0, 0);
+
+        // lump sum released at the cliff
+        testing_env!(context.block_timestamp(50).build());
+        assert_eq!(contract.get_locked_amount(accounts(2).into()).0, 450);
+
+        testing_env!(context.block_timestamp(75).build());
+        assert_eq!(contract.get_locked_amount(accounts(2).into()).0, 225);
+    }
+
+    #[test]
+    fn test_cancel_vesting() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(3).into(), 1, None);
+        let g1 = contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+        let g2 = contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+
+        testing_env!(context.block_timestamp(40).build());
+        // burn the locked part of the first grant
+        assert_eq!(
+            contract.cancel_vesting(&accounts(2).into(), g1, None).0,
+            600
+        );
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 1400);
+        assert_eq!(contract.ft_total_supply().0, 1401);
+        // transfer the locked part of the second grant to the treasury
+        assert_eq!(
+            contract
+                .cancel_vesting(&accounts(2).into(), g2, Some(accounts(3)))
+                .0,
+            600
+        );
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 800);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 601);
+        assert_eq!(contract.get_locked_amount(accounts(2).into()).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "vesting grant is irrevocable")]
+    fn test_cancel_irrevocable_vesting() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        let g = contract.mint_vested(
+            &accounts(2).into(),
+            1000.into(),
+            VestingParams {
+                revocable: Some(false),
+                ..vesting_params(0, 100)
+            },
+        );
+        contract.cancel_vesting(&accounts(2).into(), g, None);
+    }
+
+    #[test]
+    fn test_spendable_balance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+        contract.ft_mint(&accounts(2).into(), 10.into(), None);
+
+        testing_env!(context
+            .block_timestamp(40)
+            .predecessor_account_id(accounts(2))
+            .build());
+        assert_eq!(contract.ft_locked_balance_of(accounts(2)).0, 600);
+        assert_eq!(contract.ft_spendable_balance_of(accounts(2)).0, 410);
+        contract.self_burn(410.into());
+        assert_eq!(contract.ft_spendable_balance_of(accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Account with vesting, balance can't go lower than 600")]
+    fn test_burn_locked_tokens() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+
+        testing_env!(context
+            .block_timestamp(40)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.self_burn(401.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't unregister the account with locked vesting tokens")]
+    fn test_force_unregister_locked_tokens() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.storage_unregister(Some(true));
+    }
+
+    #[test]
+    fn test_transfer_from() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1000, None);
+        contract.mint(&accounts(3).into(), 1, None);
+
+        testing_env!(context
+            .attached_deposit(contract.ft_allowance_storage_cost().0)
+            .build());
+        let initial = env::storage_usage();
+        contract.ft_approve(accounts(2), 300.into(), Some(100.into()));
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 300);
+        // the deposit covers the real allowance storage
+        assert!(env::storage_usage() - initial <= contract.allowance_storage_usage);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 200.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 800);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 201);
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 100);
+
+        // allowance expired
+        testing_env!(context.block_timestamp(100).build());
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(2)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "account charlie is frozen until 100")]
+    fn test_frozen_spender_transfer_from() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1000, None);
+        contract.mint(&accounts(3).into(), 1, None);
+        testing_env!(context
+            .attached_deposit(contract.ft_allowance_storage_cost().0)
+            .build());
+        contract.ft_approve(accounts(2), 300.into(), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.freeze_account(accounts(2), 100.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "account charlie is frozen until 100")]
+    fn test_frozen_spender_transfer_from_call() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1000, None);
+        contract.mint(&accounts(3).into(), 1, None);
+        testing_env!(context
+            .attached_deposit(contract.ft_allowance_storage_cost().0)
+            .build());
+        contract.ft_approve(accounts(2), 300.into(), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.freeze_account(accounts(2), 100.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer_from_call(accounts(1), accounts(3), 100.into(), None, "".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "allowance exceeded")]
+    fn test_transfer_from_above_allowance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1000, None);
+        contract.mint(&accounts(3).into(), 1, None);
+
+        testing_env!(context
+            .attached_deposit(contract.ft_allowance_storage_cost().0)
+            .build());
+        contract.ft_approve(accounts(2), 300.into(), None);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer_from(accounts(1), accounts(3), 301.into(), None);
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        assert!(contract.has_role(Role::Admin, accounts(1)));
+        assert_eq!(contract.get_minters(), vec![accounts(1).to_string()]);
+
+        contract.grant_role(Role::MetadataManager, accounts(2));
+        assert_eq!(
+            contract.get_role_members(Role::MetadataManager),
+            vec![accounts(1).to_string(), accounts(2).to_string()]
+        );
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_metadata_icon("icon".to_string());
+        assert_eq!(contract.ft_metadata().icon.unwrap(), "icon");
+        contract.renounce_role(Role::MetadataManager);
+        assert!(contract.get_account_roles(accounts(2)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role: admin")]
+    fn test_grant_role_not_admin() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.grant_role(Role::Admin, accounts(2));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.grant_role(Role::Minter, accounts(3));
+    }
+
+    #[test]
+    fn test_ownership_transfer() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.propose_owner(accounts(2), Some(100.into()));
+        assert_eq!(contract.get_owner(), accounts(1).to_string());
+        assert_eq!(
+            contract.get_pending_owner().unwrap().account_id,
+            accounts(2).to_string()
+        );
+
+        testing_env!(context
+            .block_timestamp(100)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner(), accounts(2).to_string());
+        assert!(contract.get_pending_owner().is_none());
+        assert!(contract.has_role(Role::Admin, accounts(2)));
+        assert!(!contract.has_role(Role::Admin, accounts(1)));
+        // roles and the minter allowance are moved to the new owner
+        assert_eq!(contract.get_account_roles(accounts(1)).len(), 0);
+        assert_eq!(contract.get_account_roles(accounts(2)).len(), 5);
+        assert!(contract.get_minter_allowance(accounts(1).into()).is_none());
+        contract.ft_mint(&accounts(3).into(), 10.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role: minter")]
+    fn test_previous_owner_cant_mint() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.propose_owner(accounts(2), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_mint(&accounts(3).into(), 10.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "missing role: upgrader")]
+    fn test_previous_owner_cant_stage_upgrade() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.propose_owner(accounts(2), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.accept_ownership();
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.internal_stage_upgrade(&[1u8, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ownership can't be accepted before 100")]
+    fn test_accept_ownership_before_delay() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.propose_owner(accounts(2), Some(100.into()));
+
+        testing_env!(context
+            .block_timestamp(99)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100, None);
+        contract.grant_role(Role::Pauser, accounts(2));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.pause(PauseFeature::Mint);
+        assert!(contract.is_paused(PauseFeature::Mint));
+        assert!(!contract.is_paused(PauseFeature::Transfer));
+        contract.pause(PauseFeature::All);
+        assert!(contract.is_paused(PauseFeature::Transfer));
+        contract.unpause(PauseFeature::All);
+
+        // transfers and burns still work
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.self_burn(10.into());
+        assert_eq!(contract.ft_total_supply().0, 90);
+    }
+
+    #[test]
+    #[should_panic(expected = "minting is paused")]
+    fn test_mint_paused() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.grant_role(Role::Pauser, accounts(2));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.pause(PauseFeature::Mint);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_mint(&accounts(1).into(), 10.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "guardian must be distinct from the owner")]
+    fn test_owner_guardian() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.grant_role(Role::Pauser, accounts(1));
+    }
+
+    #[test]
+    fn test_balance_checkpoints() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_index(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100, None);
+        contract.mint(&accounts(2).into(), 1, None);
+        let cost = contract.get_history_storage_cost(10).0;
+        assert!(cost > 0);
+        testing_env!(context.attached_deposit(cost).build());
+        contract.enable_balance_history(10);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.enable_balance_history(2);
+
+        testing_env!(context
+            .attached_deposit(1)
+            .block_index(20)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.ft_transfer(accounts(2), 30.into(), None);
+        contract.ft_transfer(accounts(2), 10.into(), None);
+
+        testing_env!(context.block_index(30).build());
+        contract.self_burn(60.into());
+
+        assert_eq!(contract.ft_balance_at(accounts(1), 10.into()).0, 100);
+        assert_eq!(contract.ft_balance_at(accounts(1), 19.into()).0, 100);
+        assert_eq!(contract.ft_balance_at(accounts(1), 25.into()).0, 60);
+        assert_eq!(contract.ft_balance_at(accounts(1), 30.into()).0, 0);
+        assert_eq!(contract.ft_balance_at(accounts(2), 20.into()).0, 41);
+        assert_eq!(contract.ft_total_supply_at(15.into()).0, 101);
+        assert_eq!(contract.ft_total_supply_at(30.into()).0, 41);
+
+        // the oldest checkpoint is pruned when the history is full
+        testing_env!(context
+            .block_index(40)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(1), 1.into(), None);
+        let h = contract.get_balance_history(accounts(2)).unwrap();
+        assert_eq!(h.len, 2);
+        assert_eq!(h.oldest_block, Some(20.into()));
+        assert_eq!(h.deposit.0, contract.get_history_storage_cost(2).0);
+
+        // closing the account removes its history
+        testing_env!(context.block_index(50).build());
+        contract.close_account(Some(accounts(1)), None);
+        assert!(contract.get_balance_history(accounts(2)).is_none());
+        assert!(contract
+            .balance_checkpoints
+            .get(&accounts(2).into())
+            .is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "history is not available before block 10")]
+    fn test_balance_history_before_enabled() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_index(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100, None);
+        testing_env!(context
+            .attached_deposit(contract.get_history_storage_cost(1).0)
+            .build());
+        contract.enable_balance_history(1);
+        contract.ft_balance_at(accounts(1), 9.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "balance history of charlie is not enabled")]
+    fn test_balance_history_not_enabled() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_index(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(2).into(), 100, None);
+        contract.ft_balance_at(accounts(2), 10.into());
+    }
+
+    #[test]
+    fn test_delegation() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_index(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100, None);
+        contract.mint(&accounts(2).into(), 50, None);
+        let cost = contract.get_history_storage_cost(10).0;
+        testing_env!(context.attached_deposit(cost).build());
+        contract.enable_vote_history(10);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.enable_vote_history(10);
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.delegate(accounts(3), None);
+        assert_eq!(contract.delegates(accounts(1)), Some(accounts(3).into()));
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 100);
+
+        testing_env!(context.block_index(20).build());
+        contract.ft_transfer(accounts(2), 30.into(), None);
+        contract.mint(&accounts(1).into(), 5, None);
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 75);
+
+        testing_env!(context
+            .block_index(30)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.delegate(accounts(3), None);
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 155);
+        contract.self_burn(10.into());
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 145);
+
+        testing_env!(context
+            .block_index(40)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.delegate(accounts(1), None);
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 70);
+        assert_eq!(contract.get_current_votes(accounts(1)).0, 75);
+
+        assert_eq!(contract.get_prior_votes(accounts(3), 15.into()).0, 100);
+        assert_eq!(contract.get_prior_votes(accounts(3), 25.into()).0, 75);
+        assert_eq!(contract.get_prior_votes(accounts(3), 30.into()).0, 145);
+        assert_eq!(contract.get_prior_votes(accounts(1), 30.into()).0, 0);
+        assert!(get_logs()
+            .iter()
+            .any(|l| l.contains(r#""event":"delegate_changed""#)));
+    }
+
+    #[test]
+    fn test_close_account_removes_delegation() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1, None);
+        contract.mint(&accounts(2).into(), 50, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.delegate(accounts(3), None);
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 50);
+
+        contract.close_account(Some(accounts(1)), None);
+        assert_eq!(contract.delegates(accounts(2)), None);
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 0);
+        assert!(get_logs().iter().any(|l| l.contains(
+            r#""event":"delegate_changed","data":[{"delegator_id":"charlie","from_delegate":"danny","to_delegate":null}]"#
+        )));
+    }
+
+    #[test]
+    fn test_delegation_vesting() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_index(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        let g = contract.mint_vested(&accounts(2).into(), 100.into(), vesting_params(100, 200));
+        contract.mint(&accounts(2).into(), 50, None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        // vesting is excluded by default
+        contract.delegate(accounts(3), None);
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 50);
+        contract.delegate(accounts(3), Some(true));
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 150);
+        assert!(
+            contract
+                .get_delegation(accounts(2))
+                .unwrap()
+                .include_vesting
+        );
+        contract.delegate(accounts(3), Some(false));
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 50);
+
+        // votes don't change when the locked tokens are reclaimed
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.cancel_vesting(&accounts(2).into(), g, None);
+        assert_eq!(contract.get_current_votes(accounts(3)).0, 50);
+        assert_eq!(contract.get_delegation(accounts(2)).unwrap().votes.0, 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "vote history of danny is not enabled")]
+    fn test_prior_votes_history_not_enabled() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_index(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100, None);
+        contract.delegate(accounts(3), None);
+        testing_env!(context.block_index(20).build());
+        contract.get_prior_votes(accounts(3), 10.into());
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        testing_env!(context.build());
+        contract.ft_batch_mint(
+            vec![
+                (accounts(1), 100.into()),
+                (accounts(2), 10.into()),
+                (accounts(3), 5.into()),
+            ],
+            Some("airdrop".to_string()),
+        );
+        contract.ft_batch_transfer(
+            vec![(accounts(2), 20.into()), (accounts(3), 30.into())],
+            None,
+        );
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 50);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 30);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 35);
+        assert_eq!(contract.ft_total_supply().0, 115);
+        let logs = get_logs();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(
+            logs[0],
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_mint","data":[{"owner_id":"bob","amount":"100","memo":"airdrop"},{"owner_id":"charlie","amount":"10","memo":"airdrop"},{"owner_id":"danny","amount":"5","memo":"airdrop"}]}"#
+        );
+        assert_eq!(
+            logs[1],
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","amount":"20"},{"old_owner_id":"bob","new_owner_id":"danny","amount":"30"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_batch_max_size() {
+        assert!(
+            batch::GAS_PER_BATCH_ITEM * batch::MAX_BATCH_SIZE as Gas + 50 * TGAS
+                <= batch::MAX_PREPAID_GAS
+        );
+        let mut context = get_context(accounts(1));
+        testing_env!(context
+            .attached_deposit(1)
+            .prepaid_gas(batch::MAX_PREPAID_GAS)
+            .build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(2).into(), 1, None);
+        contract.ft_batch_mint(vec![(accounts(1), 1.into()); batch::MAX_BATCH_SIZE], None);
+        contract.ft_batch_transfer(vec![(accounts(2), 1.into()); batch::MAX_BATCH_SIZE], None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 101);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "batch too big, max batch size: 100")]
+    fn test_batch_too_big() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.ft_batch_mint(
+            vec![(accounts(2), 1.into()); batch::MAX_BATCH_SIZE + 1],
+            None,
+        );
+    }
+
+    #[test]
+    fn test_airdrop() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_timestamp(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.set_minter_allowance(accounts(1).into(), 1000.into());
+        let leaf_c = airdrop::airdrop_leaf(&accounts(2).into(), 10);
+        let leaf_d = airdrop::airdrop_leaf(&accounts(3).into(), 20);
+        let root = if leaf_c <= leaf_d {
+            env::sha256(&[leaf_c, leaf_d].concat())
+        } else {
+            env::sha256(&[leaf_d, leaf_c].concat())
+        };
+        let root: CryptoHash = std::convert::TryInto::try_into(root).unwrap();
+        let id = contract.create_airdrop(
+            root.into(),
+            100.into(),
+            50.into(),
+            airdrop::AirdropFunding::Mint,
+        );
+        assert_eq!(
+            contract
+                .get_minter_allowance(accounts(1).into())
+                .unwrap()
+                .allowance
+                .0,
+            900
+        );
+        let a = contract.get_minter_allowance(accounts(1).into()).unwrap();
+        assert_eq!((a.reserved.0, a.minted.0), (100, 0));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.claim_airdrop(id, 10.into(), vec![leaf_d.into()]);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+        assert!(contract.is_airdrop_claimed(id, accounts(2), 10.into()));
+        assert!(!contract.is_airdrop_claimed(id, accounts(3), 20.into()));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(50)
+            .build());
+        assert_eq!(contract.reclaim_airdrop(id).0, 90);
+        assert_eq!(
+            contract
+                .get_minter_allowance(accounts(1).into())
+                .unwrap()
+                .allowance
+                .0,
+            990
+        );
+        let a = contract.get_minter_allowance(accounts(1).into()).unwrap();
+        assert_eq!((a.reserved.0, a.minted.0), (0, 10));
+        assert!(contract.get_airdrop(id).unwrap().closed);
+    }
+
+    #[test]
+    fn test_airdrop_reclaim_after_minter_regranted() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_timestamp(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.add_minter(accounts(2).into(), 100.into());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let leaf = airdrop::airdrop_leaf(&accounts(2).into(), 10);
+        let id = contract.create_airdrop(
+            leaf.into(),
+            100.into(),
+            50.into(),
+            airdrop::AirdropFunding::Mint,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.remove_minter(&accounts(2).into());
+        contract.add_minter(accounts(2).into(), 10.into());
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(50)
+            .build());
+        assert_eq!(contract.reclaim_airdrop(id).0, 100);
+        // the reservation was dropped with the revoked role
+        let a = contract.get_minter_allowance(accounts(2).into()).unwrap();
+        assert_eq!((a.allowance.0, a.reserved.0, a.minted.0), (10, 0, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "airdrop creator charlie is no longer a minter")]
+    fn test_airdrop_claim_after_minter_revoked() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_timestamp(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.add_minter(accounts(2).into(), 100.into());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let leaf = airdrop::airdrop_leaf(&accounts(3).into(), 10);
+        let id = contract.create_airdrop(
+            leaf.into(),
+            100.into(),
+            50.into(),
+            airdrop::AirdropFunding::Mint,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.remove_minter(&accounts(2).into());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.claim_airdrop(id, 10.into(), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "minter reservation exceeded, reserved: 0")]
+    fn test_airdrop_claim_after_minter_regranted() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).block_timestamp(10).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.add_minter(accounts(2).into(), 100.into());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let leaf = airdrop::airdrop_leaf(&accounts(3).into(), 10);
+        let id = contract.create_airdrop(
+            leaf.into(),
+            100.into(),
+            50.into(),
+            airdrop::AirdropFunding::Mint,
+        );
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.remove_minter(&accounts(2).into());
+        contract.add_minter(accounts(2).into(), 10.into());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.claim_airdrop(id, 10.into(), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid merkle proof")]
+    fn test_airdrop_invalid_proof() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        let leaf = airdrop::airdrop_leaf(&accounts(2).into(), 10);
+        let id = contract.create_airdrop(
+            leaf.into(),
+            10.into(),
+            50.into(),
+            airdrop::AirdropFunding::Mint,
+        );
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.claim_airdrop(id, 20.into(), vec![]);
+    }
+
+    #[test]
+    fn test_sponsored_storage() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.fund_sponsor_pool();
+        let r = contract.get_storage_report();
+        assert!(r.account_storage_usage > 0);
+        assert_eq!(r.sponsor_pool.0, 10u128.pow(24));
+
+        contract.mint(&accounts(2).into(), 10, None);
+        let r2 = contract.get_storage_report();
+        assert_eq!(
+            r2.sponsored_storage,
+            r.sponsored_storage + r.account_storage_usage
+        );
+        assert_eq!(
+            r2.sponsor_pool.0,
+            10u128.pow(24) - r.account_storage_usage as u128 * env::storage_byte_cost()
+        );
+
+        contract.storage_deposit(Some(accounts(3)), None);
+        let r3 = contract.get_storage_report();
+        assert!(r3.user_paid_storage > 0);
+        assert_eq!(r3.sponsor_pool, r2.sponsor_pool);
+
+        // closing a sponsored account returns to the pool what the pool paid
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.close_account(None, Some(true));
+        assert_eq!(contract.get_storage_report().sponsor_pool.0, 10u128.pow(24));
+
+        // an account registered when the pool was empty doesn't credit the pool
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.withdraw_sponsor_pool(10u128.pow(24).into());
+        contract.mint(&accounts(4).into(), 10, None);
+        testing_env!(context.attached_deposit(10).build());
+        contract.fund_sponsor_pool();
+        testing_env!(context
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(4))
+            .build());
+        contract.close_account(None, Some(true));
+        assert_eq!(contract.get_storage_report().sponsor_pool.0, 10);
+    }
+
+    #[test]
+    fn test_close_account() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 10, None);
+        contract.mint(&accounts(2).into(), 100, None);
+        contract.mint(&accounts(3).into(), 50, None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert!(contract.close_account(Some(accounts(1)), None));
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 110);
+        assert!(contract.storage_balance_of(accounts(2)).is_none());
+        assert!(get_logs()[0].contains(r#""memo":"account closure""#));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        assert!(contract.storage_unregister(Some(true)));
+        assert_eq!(contract.ft_total_supply().0, 110);
+        assert_eq!(
+            get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_burn","data":[{"owner_id":"danny","amount":"50","memo":"force storage unregister"}]}"#
+            ]
+        );
+        assert!(!contract.storage_unregister(None));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Can't unregister the account with the positive balance without force"
+    )]
+    fn test_unregister_positive_balance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 10, None);
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_staged_upgrade() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        let code = vec![1u8, 2, 3];
+        contract.internal_stage_upgrade(&code);
+        assert_eq!(
+            contract.get_staged_code_hash().unwrap(),
+            "039058c6f2c0cb492c533b0a4d14ef77cc0f78abccced5287d84a1a2011cfb81"
+        );
+
+        testing_env!(context.attached_deposit(1).build());
+        contract.cancel_staged_upgrade();
+        assert!(contract.get_staged_code_hash().is_none());
+
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.internal_stage_upgrade(&code);
+        testing_env!(context
+            .attached_deposit(1)
+            .block_timestamp(upgrade::UPGRADE_DELAY)
+            .build());
+        contract.deploy_staged_upgrade();
+        assert!(contract.get_staged_upgrade().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "staged upgrade can't be deployed before")]
+    fn test_deploy_upgrade_before_delay() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.internal_stage_upgrade(&[1u8, 2, 3]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.deploy_staged_upgrade();
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let m = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Cheddar".to_string(),
+            symbol: "Cheddar".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 24,
+        };
+        let mut v1 = migrations::StateV1 {
+            metadata: LazyOption::new(b"m".to_vec(), Some(&m)),
+            accounts: LookupMap::new(b"a".to_vec()),
+            owner_id: accounts(1).into(),
+            minters: vec![accounts(3).into()],
+            total_supply: 100,
+            vested: LookupMap::new(b"v".to_vec()),
+        };
+        v1.accounts.insert(
+            &accounts(2).into(),
+            &storage::AccBalance {
+                near: 0,
+                token: 100,
+            },
+        );
+        v1.vested.insert(
+            &accounts(2).into(),
+            &OldVestingRecord {
+                amount: 100,
+                cliff_timestamp: 10,
+                end_timestamp: 20,
+            },
+        );
+        env::state_write(&v1);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), migrations::STATE_VERSION);
+        assert_eq!(contract.get_owner_id(), AccountId::from(accounts(1)));
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 100);
+        assert_eq!(contract.ft_total_supply().0, 100);
+        assert_eq!(contract.ft_max_supply().0, u128::MAX);
+        assert!(contract.has_role(Role::Minter, accounts(3)));
+        assert!(contract.has_role(Role::Upgrader, accounts(1)));
+        assert_eq!(
+            contract
+                .get_minter_allowance(accounts(3).into())
+                .unwrap()
+                .allowance
+                .0,
+            u128::MAX
+        );
+        let g = contract.get_vesting_info(accounts(2).into());
+        assert_eq!(g.len(), 1);
+        assert_eq!((g[0].id, g[0].amount.0, g[0].end_timestamp.0), (0, 100, 20));
+        assert_eq!(contract.ft_locked_balance_of(accounts(2)).0, 100);
+
+        // the legacy grant gets a new id when it's converted
+        contract.refresh_vesting_locks(vec![accounts(2)]);
+        let g = contract.get_vesting_info(accounts(2).into());
+        assert_eq!((g[0].id, g[0].amount.0), (1, 100));
+        assert_eq!(contract.next_vesting_id, 2);
+        assert!(contract.account_storage_usage > 0);
+        assert_eq!(contract.ft_total_supply_at(0.into()).0, 100);
+
+        // holders are counted only after the backfill
+        assert_eq!(contract.ft_holder_count(), None);
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .build());
+        assert!(!contract.get_supply_breakdown().backfill_complete);
+        contract.backfill_holders(vec![accounts(2), accounts(3)]);
+        contract.finish_holders_backfill();
+        assert_eq!(contract.ft_holder_count(), Some(1));
+        let b = contract.get_supply_breakdown();
+        assert!(b.backfill_complete);
+        assert_eq!(b.vesting_locked.0, 100);
+    }
+
+    /// Stores the state of a new contract in the layout of `version` and migrates it to
+    /// the current version.
+    fn migrate_from_version(version: u32) -> Contract {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(2).into(), 10, None);
+        env::state_write(&contract);
+        let state: migrations::StateV7 = env::state_read().unwrap();
+        match version {
+            2 => env::state_write(&state.v6.v5.v4.v3.v2),
+            3 => env::state_write(&state.v6.v5.v4.v3),
+            4 => env::state_write(&state.v6.v5.v4),
+            5 => env::state_write(&state.v6.v5),
+            6 => env::state_write(&state.v6),
+            _ => unreachable!(),
+        };
+        migrations::write_state_version(version);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), migrations::STATE_VERSION);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+        // the migrated state is stored in the current layout
+        let bytes = env::storage_read(b"STATE").unwrap();
+        let state = migrations::StateV7::try_from_slice(&bytes).unwrap();
+        assert_eq!(state.try_to_vec().unwrap(), bytes);
+        contract
+    }
+
+    #[test]
+    fn test_migrate_v2() {
+        let contract = migrate_from_version(2);
+        assert_eq!(contract.get_next_bridge_nonce().0, 1);
+        assert!(!contract.is_holders_backfill_complete());
+        assert_eq!(contract.total_minted, 10);
+        assert!(contract.get_transfer_fee().is_none());
+    }
+
+    #[test]
+    fn test_migrate_v3() {
+        let contract = migrate_from_version(3);
+        // the holder index is introduced by this step
+        assert!(!contract.is_holders_backfill_complete());
+        assert_eq!(contract.ft_holder_count(), None);
+        assert_eq!(contract.total_minted, 10);
+    }
+
+    #[test]
+    fn test_migrate_v4() {
+        let contract = migrate_from_version(4);
+        // the finished holder backfill is kept
+        assert!(contract.is_holders_backfill_complete());
+        assert_eq!(contract.ft_holder_count(), Some(1));
+        assert_eq!(contract.total_minted, 10);
+        assert_eq!(contract.total_burned, 0);
+        assert_eq!(contract.total_vesting_locked, 0);
+    }
+
+    #[test]
+    fn test_migrate_v5() {
+        let contract = migrate_from_version(5);
+        assert!(contract.is_holders_backfill_complete());
+        assert_eq!(contract.total_minted, 10);
+        assert!(!contract.is_frozen(accounts(2)));
+        assert!(contract.get_transfer_fee().is_none());
+    }
+
+    #[test]
+    fn test_migrate_v6() {
+        let contract = migrate_from_version(6);
+        assert!(contract.is_holders_backfill_complete());
+        assert_eq!(contract.total_minted, 10);
+        assert!(contract.get_transfer_fee().is_none());
+    }
+
+    #[test]
+    fn test_state_layout() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(2).into(), 10, None);
+        // the latest versioned state must have the same layout as the contract
+        let bytes = contract.try_to_vec().unwrap();
+        let state = migrations::StateV7::try_from_slice(&bytes).unwrap();
+        assert_eq!(state.try_to_vec().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_migrate_current_version() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(2).into(), 10, None);
+        env::state_write(&contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), migrations::STATE_VERSION);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10);
+    }
+
+    #[test]
+    fn test_bridge() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.grant_role(Role::BridgeMinter, accounts(2));
+        contract.set_bridge_minter_allowance(accounts(2).into(), 30.into());
+        contract.mint(&accounts(1).into(), 100, None);
+
+        let nonce = contract.ft_burn_for_bridge(30.into(), "aurora".into(), "0xabc".into());
+        assert_eq!(nonce.0, 1);
+        assert_eq!(contract.get_next_bridge_nonce().0, 2);
+        assert_eq!(contract.ft_total_supply().0, 70);
+        assert!(get_logs().last().unwrap().contains(
+            r#""event":"bridge_withdrawal","data":[{"nonce":"1","account_id":"bob","amount":"30","destination_chain":"aurora","recipient":"0xabc"}]"#
+        ));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.bridge_mint(accounts(3), 20.into(), "aurora".into(), "0x01".into());
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 20);
+        assert!(contract.is_bridge_deposit_minted("aurora".into(), "0x01".into()));
+        assert!(!contract.is_bridge_deposit_minted("ethereum".into(), "0x01".into()));
+        contract.bridge_mint(accounts(3), 10.into(), "aurora".into(), "a:b".into());
+        assert!(!contract.is_bridge_deposit_minted("aurora:a".into(), "b".into()));
+        let a = contract
+            .get_bridge_minter_allowance(accounts(2).into())
+            .unwrap();
+        assert_eq!((a.allowance.0, a.minted.0), (0, 30));
+        // the bridge allowance is separate from the minter allowance
+        assert!(contract.get_minter_allowance(accounts(2).into()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "minter allowance exceeded, remaining allowance: 10")]
+    fn test_bridge_mint_allowance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.grant_role(Role::BridgeMinter, accounts(1));
+        contract.set_bridge_minter_allowance(accounts(1).into(), 10.into());
+        contract.bridge_mint(accounts(3), 20.into(), "aurora".into(), "0x01".into());
+    }
+
+    #[test]
+    #[should_panic(expected = "bridge deposit already minted")]
+    fn test_bridge_mint_twice() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.grant_role(Role::BridgeMinter, accounts(1));
+        contract.set_bridge_minter_allowance(accounts(1).into(), 100.into());
+        contract.bridge_mint(accounts(3), 20.into(), "aurora".into(), "0x01".into());
+        contract.bridge_mint(accounts(3), 20.into(), "aurora".into(), "0x01".into());
+    }
+
+    #[test]
+    fn test_holders() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100, None);
+        contract.mint(&accounts(2).into(), 10, None);
+        contract.mint(&accounts(3).into(), 50, None);
+        // registered account without balance is not a holder
+        contract.mint(&accounts(4).into(), 0, None);
+        assert_eq!(contract.ft_holder_count(), Some(3));
+
+        contract.ft_transfer(accounts(4), 100.into(), None);
+        assert_eq!(contract.ft_holder_count(), Some(3));
+        let holders: Vec<AccountId> = contract
+            .ft_holders(None, None)
+            .into_iter()
+            .map(|h| h.account_id)
+            .collect();
+        assert!(!holders.contains(&accounts(1).into()));
+        assert_eq!(contract.ft_holders(Some(1), Some(1)).len(), 1);
+
+        let top = contract.ft_top_holders(2, None);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].account_id, AccountId::from(accounts(4)));
+        assert_eq!(top[1].balance.0, 50);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.storage_unregister(Some(true));
+        assert_eq!(contract.ft_holder_count(), Some(2));
+    }
+
+    #[test]
+    fn test_supply_breakdown() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1000, None);
+        contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+        contract.mint(&accounts(3).into(), 300, None);
+        contract.add_excluded_account(accounts(3));
+        contract.self_burn(100.into());
+
+        let b = contract.get_supply_breakdown();
+        assert_eq!(b.total_supply.0, 2200);
+        assert_eq!(b.total_minted.0, 2300);
+        assert_eq!(b.total_burned.0, 100);
+        assert_eq!(b.vesting_locked.0, 1000);
+        assert_eq!(b.excluded.0, 300);
+        assert_eq!(b.circulating_supply.0, 900);
+        assert_eq!(contract.ft_circulating_supply().0, 900);
+        // only the vested grant was minted with `ft_mint`
+        assert_eq!(b.minted_by_minter[0].minted.0, 1000);
+        assert!(b.backfill_complete);
+
+        // vesting of excluded accounts is part of their balance
+        contract.mint_vested(&accounts(3).into(), 100.into(), vesting_params(0, 100));
+        assert_eq!(contract.ft_circulating_supply().0, 900);
+
+        // vested tokens are counted once the vesting lock is refreshed
+        testing_env!(context.block_timestamp(40).build());
+        assert_eq!(contract.ft_circulating_supply().0, 900);
+        assert_eq!(contract.refresh_vesting_locks(vec![accounts(2)]).0, 600);
+        assert_eq!(contract.ft_circulating_supply().0, 1300);
+
+        // excluding an account moves its recorded lock out of the vesting total
+        contract.add_excluded_account(accounts(2));
+        let b = contract.get_supply_breakdown();
+        assert_eq!(b.vesting_locked.0, 0);
+        assert_eq!(b.excluded.0, 1400);
+        assert_eq!(b.circulating_supply.0, 900);
+        contract.remove_excluded_account(accounts(2));
+        assert_eq!(contract.get_supply_breakdown().vesting_locked.0, 600);
+        assert_eq!(contract.ft_circulating_supply().0, 1300);
+
+        // minted amounts are kept when the minter role is revoked
+        contract.remove_minter(&accounts(1).into());
+        let b = contract.get_supply_breakdown();
+        assert_eq!(
+            b.minted_by_minter[0].account_id,
+            AccountId::from(accounts(1))
+        );
+        assert_eq!(b.minted_by_minter[0].minted.0, 1100);
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.grant_role(Role::Pauser, accounts(3));
+        contract.mint(&accounts(2).into(), 100, None);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.freeze_account(accounts(2), 100.into(), Some("exploit".to_string()));
+        assert!(contract.is_frozen(accounts(2)));
+        assert_eq!(contract.get_frozen_until(accounts(2)).unwrap().0, 100);
+        assert_eq!(
+            get_logs()[0],
+            r#"EVENT_JSON:{"standard":"cheddar","version":"1.0.0","event":"account_frozen","data":[{"account_id":"charlie","frozen_until":"100","reason":"exploit","by":"danny"}]}"#
+        );
+
+        // the freeze expires automatically
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(100)
+            .build());
+        assert!(!contract.is_frozen(accounts(2)));
+        contract.ft_transfer(accounts(1), 10.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 90);
+    }
+
+    #[test]
+    #[should_panic(expected = "account charlie is frozen until 100")]
+    fn test_frozen_transfer() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 1, None);
+        contract.mint(&accounts(2).into(), 100, None);
+        contract.freeze_account(accounts(2), 100.into(), None);
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(1), 10.into(), None);
+    }
+
+    #[test]
+    fn test_cancel_vesting_frozen() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(3).into(), 1, None);
+        let g1 = contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+        let g2 = contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+        contract.freeze_account(accounts(2), 100.into(), None);
+
+        // vesting managers can reclaim locked tokens from a frozen account
+        testing_env!(context.block_timestamp(40).build());
+        assert_eq!(
+            contract.cancel_vesting(&accounts(2).into(), g1, None).0,
+            600
+        );
+        assert_eq!(
+            contract
+                .cancel_vesting(&accounts(2).into(), g2, Some(accounts(3)))
+                .0,
+            600
+        );
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 800);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 601);
+        assert_eq!(contract.ft_total_supply().0, 1401);
+        assert!(contract.is_frozen(accounts(2)));
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100_000, None);
+        contract.mint(&accounts(3).into(), 1, None);
+        contract.mint(&accounts(4).into(), 1, None);
+        // 1%, at most 50 tokens per transfer
+        contract.set_transfer_fee(100, 50.into(), accounts(3));
+        contract.add_fee_exempt_account(accounts(4));
+        assert_eq!(
+            contract
+                .ft_transfer_fee(accounts(1), accounts(2), 1000.into())
+                .0,
+            10
+        );
+
+        testing_env!(context.build());
+        contract.ft_transfer(accounts(2), 1000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 990);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 11);
+        assert_eq!(
+            get_logs()[0],
+            r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer","data":[{"old_owner_id":"bob","new_owner_id":"charlie","amount":"990"},{"old_owner_id":"bob","new_owner_id":"danny","amount":"10","memo":"transfer fee"}]}"#
+        );
+
+        // the fee is capped
+        contract.ft_transfer(accounts(2), 10_000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 10_940);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 61);
+
+        // exempted accounts are not charged
+        contract.ft_transfer(accounts(4), 1000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 1001);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 61);
+
+        // the fee is charged for each batch transfer
+        contract.ft_batch_transfer(
+            vec![(accounts(2), 1000.into()), (accounts(4), 1000.into())],
+            None,
+        );
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 11_930);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 71);
+
+        contract.remove_transfer_fee();
+        contract.ft_transfer(accounts(2), 1000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 12_930);
+        assert!(contract.get_transfer_fee().is_none());
+    }
+
+    #[test]
+    fn test_transfer_call_fee() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100_000, None);
+        contract.mint(&accounts(2).into(), 1, None);
+        contract.mint(&accounts(3).into(), 1, None);
+        contract.set_transfer_fee(100, 50.into(), accounts(3));
+
+        contract.ft_transfer_call(accounts(2), 1000.into(), None, "".to_string());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 991);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 11);
+
+        // the receiver returns 90 unused tokens, the fee is reported as used
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::default(),
+            near_sdk::RuntimeFeesConfig::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"90\"".to_vec())]
+        );
+        let used =
+            contract.ft_resolve_transfer_with_fee(accounts(1), accounts(2), 990.into(), 10.into());
+        assert_eq!(used.0, 910);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 901);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 11);
+    }
+
+    #[test]
+    fn test_resolve_transfer_gas() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(1).into(), 100_000, None);
+        contract.mint(&accounts(2).into(), 1, None);
+        contract.mint(&accounts(3).into(), 1, None);
+        contract.set_transfer_fee(100, 50.into(), accounts(3));
+        // worst case: both accounts have the balance history enabled and delegate their
+        // votes to a delegatee with the vote history enabled
+        let capacity = checkpoints::MAX_HISTORY_CAPACITY;
+        let cost = contract.get_history_storage_cost(capacity).0;
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(cost)
+            .build());
+        contract.enable_vote_history(capacity);
+        for a in [accounts(1), accounts(2)].iter() {
+            testing_env!(context
+                .predecessor_account_id(a.clone())
+                .attached_deposit(cost)
+                .build());
+            contract.enable_balance_history(capacity);
+            testing_env!(context.attached_deposit(1).build());
+            contract.delegate(accounts(4), None);
+        }
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(1)
+            .block_index(10)
+            .build());
+        contract.ft_transfer_call(accounts(2), 1000.into(), None, "".to_string());
+
+        // the receiver returns a part of the tokens
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .block_index(11)
+                .build(),
+            near_sdk::VMConfig::default(),
+            near_sdk::RuntimeFeesConfig::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"500\"".to_vec())]
+        );
+        contract.ft_resolve_transfer_with_fee(accounts(1), accounts(2), 990.into(), 10.into());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 491);
+        // host function costs must leave enough gas for the wasm execution
+        let used = env::used_gas();
+        assert!(
+            used * 2 <= GAS_FOR_RESOLVE_TRANSFER,
+            "ft_resolve_transfer used {} gas",
+            used
+        );
+    }
+
+    #[test]
+    fn test_transfer_fee_internal_moves() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(3).into(), 1, None);
+        contract.mint(&accounts(4).into(), 1, None);
+        let g = contract.mint_vested(&accounts(2).into(), 1000.into(), vesting_params(0, 100));
+        contract.set_transfer_fee(100, 50.into(), accounts(3));
+
+        // vesting reclaims are not charged
+        testing_env!(context.block_timestamp(40).build());
+        assert_eq!(
+            contract
+                .cancel_vesting(&accounts(2).into(), g, Some(accounts(4)))
+                .0,
+            600
+        );
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 601);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1);
+
+        // account closure sweeps are not charged
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert!(contract.close_account(Some(accounts(4)), None));
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 1001);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't unregister the transfer fee treasury")]
+    fn test_close_fee_treasury() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        let mut contract = Contract::new(accounts(1).into(), OWNER_SUPPLY.into());
+        contract.mint(&accounts(3).into(), 1, None);
+        contract.set_transfer_fee(100, 50.into(), accounts(3));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.storage_unregister(Some(true));
+    }
 }
@@ -4,61 +4,332 @@
 //-----------------------------
 
 use crate::storage::AccBalance;
+use crate::vesting::{Timestamp, VestingSchedule};
 use crate::*;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
-use near_sdk::near_bindgen;
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet};
+use near_sdk::{near_bindgen, CryptoHash, StorageUsage};
+/// Vesting record layout used before accounts could have multiple vesting grants.
+/// Records of this type are still stored in `Contract.legacy_vested`. The v1 vesting map
+/// can't be enumerated, so they are converted lazily: on the first update of the account
+/// vesting the record gets a new grant id from `next_vesting_id`. Until then it's reported
+/// with grant id 0.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldVestingRecord {
+    pub amount: u128,
+    pub cliff_timestamp: Timestamp,
+    pub end_timestamp: Timestamp,
+}
+
+impl OldVestingRecord {
+    pub fn to_vesting_record(&self, id: u64) -> VestingRecord {
+        // previously tokens were fully locked until the cliff and then linearly released
+        VestingRecord::new(
+            id,
+            self.amount,
+            self.cliff_timestamp,
+            self.cliff_timestamp,
+            self.end_timestamp,
+            VestingSchedule::Linear,
+            0,
+        )
+    }
+}
+
+//-----------------------------
+// State versions
+//-----------------------------
+
+/// Version of the current `Contract` state layout.
+/// When a release changes the layout: keep the previous layout as `StateV<N>`, bump this
+/// constant and add a `migrate_v<N>_to_v<N+1>` step to `MIGRATIONS`.
+pub const STATE_VERSION: u32 = 7;
+
+/// Storage key of the state version. Contracts deployed before the state versioning
+/// don't have this key and are at version 1.
+const STATE_VERSION_KEY: &[u8] = b"VERSION";
+
+/// Migration steps: `MIGRATIONS[i]` migrates the state from version `i + 1` to `i + 2`.
+/// Each step reads the state stored in the old layout and writes it in the new layout.
+const MIGRATIONS: [fn(); (STATE_VERSION - 1) as usize] = [
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+];
+
+pub(crate) fn read_state_version() -> u32 {
+    match env::storage_read(STATE_VERSION_KEY) {
+        Some(v) => u32::try_from_slice(&v).expect("invalid state version"),
+        None => 1,
+    }
+}
+
+pub(crate) fn write_state_version(version: u32) {
+    env::storage_write(STATE_VERSION_KEY, &version.try_to_vec().unwrap());
+}
+
 //---------------------------------------------------
-//  PREVIOUS Main Contract State for state migrations
+//  PREVIOUS Main Contract States for state migrations
 //---------------------------------------------------
-#[near_bindgen]
+
+/// v1: the initial Cheddar layout.
 #[derive(BorshDeserialize, BorshSerialize)]
-struct OldState {
-    metadata: LazyOption<FungibleTokenMetadata>,
+pub(crate) struct StateV1 {
+    pub metadata: LazyOption<FungibleTokenMetadata>,
 
     pub accounts: LookupMap<AccountId, AccBalance>,
     pub owner_id: AccountId,
     pub minters: Vec<AccountId>,
     pub total_supply: Balance,
-    pub vested: LookupMap<AccountId, VestingRecord>,
+    pub vested: LookupMap<AccountId, OldVestingRecord>,
+}
+
+/// v2: roles, minter allowances, multiple vesting grants, allowances, max supply, pause,
+/// checkpoints, delegation, airdrops, storage accounting and staged upgrades.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StateV2 {
+    pub metadata: LazyOption<FungibleTokenMetadata>,
+
+    pub accounts: LookupMap<AccountId, AccBalance>,
+    pub owner_id: AccountId,
+    pub pending_owner: Option<PendingOwner>,
+    pub roles: LookupMap<Role, Vec<AccountId>>,
+    pub total_supply: Balance,
+    pub legacy_vested: LookupMap<AccountId, OldVestingRecord>,
+    pub vested: LookupMap<AccountId, Vec<VestingRecord>>,
+    pub next_vesting_id: u64,
+    pub minter_allowances: LookupMap<AccountId, MinterAllowance>,
+    pub allowances: LookupMap<(AccountId, AccountId), Allowance>,
+    pub allowance_storage_usage: StorageUsage,
+    pub max_supply: Balance,
+    pub paused: PauseStatus,
+    pub balance_checkpoints: LookupMap<AccountId, History>,
+    pub supply_checkpoints: History,
+    pub history_storage_usage: StorageUsage,
+    pub checkpoint_storage_usage: StorageUsage,
+    pub delegates: LookupMap<AccountId, Delegation>,
+    pub votes: LookupMap<AccountId, Balance>,
+    pub vote_checkpoints: LookupMap<AccountId, History>,
+    pub airdrops: LookupMap<u64, AirdropCampaign>,
+    pub airdrop_claims: LookupSet<(u64, CryptoHash)>,
+    pub next_airdrop_id: u64,
+    pub account_storage_usage: StorageUsage,
+    pub sponsor_pool: Balance,
+    pub sponsored_storage: StorageUsage,
+    pub sponsored_accounts: LookupMap<AccountId, Balance>,
+    pub user_paid_storage: StorageUsage,
+    pub staged_upgrade: Option<StagedUpgrade>,
+}
+
+/// v3: bridge. Fields of each version are appended to the previous layout (borsh
+/// serializes nested structs without any framing).
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StateV3 {
+    pub v2: StateV2,
+    pub bridge_minter_allowances: LookupMap<AccountId, MinterAllowance>,
+    pub next_bridge_nonce: u64,
+    pub bridge_deposits: LookupSet<Vec<u8>>,
+}
+
+/// v4: holder index.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StateV4 {
+    pub v3: StateV3,
+    pub holders: UnorderedSet<AccountId>,
+    pub holders_backfill_complete: bool,
+}
+
+/// v5: supply accounting.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StateV5 {
+    pub v4: StateV4,
+    pub total_minted: Balance,
+    pub total_burned: Balance,
+    pub vesting_locked: LookupMap<AccountId, Balance>,
+    pub total_vesting_locked: Balance,
+    pub minted_by_minter: UnorderedMap<AccountId, Balance>,
+    pub excluded_accounts: UnorderedSet<AccountId>,
+}
+
+/// v6: account freezes.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StateV6 {
+    pub v5: StateV5,
+    pub frozen: LookupMap<AccountId, u64>,
+}
+
+/// v7: transfer fee. Same layout as the current `Contract`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StateV7 {
+    pub v6: StateV6,
+    pub transfer_fee: Option<TransferFee>,
+    pub fee_exempt: UnorderedSet<AccountId>,
+}
+
+//-----------------------------
+// Migration steps
+//-----------------------------
+
+/// v1 -> v2: roles, minter allowances, multiple vesting grants, allowances, max supply,
+/// pause, checkpoints, delegation, airdrops, storage accounting and staged upgrades.
+fn migrate_v1_to_v2() {
+    let old: StateV1 = env::state_read().expect("Old state doesn't exist");
+    // previous minters didn't have any limits
+    let mut minter_allowances = LookupMap::new(b"l".to_vec());
+    for m in old.minters.iter() {
+        minter_allowances.insert(m, &MinterAllowance::new(u128::MAX));
+    }
+    // the owner keeps all previous admin capabilities
+    let mut roles = LookupMap::new(b"r".to_vec());
+    roles.insert(&Role::Minter, &old.minters);
+    for role in [Role::VestingManager, Role::MetadataManager, Role::Upgrader].iter() {
+        roles.insert(role, &vec![old.owner_id.clone()]);
+    }
+    // history starts with the migration
+    let mut supply_checkpoints = History::new(b"s".to_vec(), SUPPLY_HISTORY_CAPACITY, 0);
+    supply_checkpoints.push(old.total_supply);
+    env::state_write(&StateV2 {
+        metadata: old.metadata,
+        accounts: old.accounts,
+        owner_id: old.owner_id,
+        pending_owner: None,
+        roles,
+        total_supply: old.total_supply,
+        // old vesting records are converted lazily
+        legacy_vested: old.vested,
+        vested: LookupMap::new(b"w".to_vec()),
+        next_vesting_id: 1,
+        minter_allowances,
+        allowances: LookupMap::new(b"p".to_vec()),
+        // storage usages are measured at the end of the migration
+        allowance_storage_usage: 0,
+        // previously there was no limit. Owner should lower it with `set_max_supply`.
+        max_supply: u128::MAX,
+        paused: PauseStatus::default(),
+        balance_checkpoints: LookupMap::new(b"c".to_vec()),
+        supply_checkpoints,
+        history_storage_usage: 0,
+        checkpoint_storage_usage: 0,
+        delegates: LookupMap::new(b"d".to_vec()),
+        votes: LookupMap::new(b"b".to_vec()),
+        vote_checkpoints: LookupMap::new(b"o".to_vec()),
+        airdrops: LookupMap::new(b"e".to_vec()),
+        airdrop_claims: LookupSet::new(b"f".to_vec()),
+        next_airdrop_id: 1,
+        account_storage_usage: 0,
+        sponsor_pool: 0,
+        // storage of the accounts registered before the migration is not accounted.
+        sponsored_storage: 0,
+        sponsored_accounts: LookupMap::new(b"n".to_vec()),
+        user_paid_storage: 0,
+        staged_upgrade: None,
+    });
+}
+
+/// v2 -> v3: bridge.
+fn migrate_v2_to_v3() {
+    let old: StateV2 = env::state_read().expect("Old state doesn't exist");
+    env::state_write(&StateV3 {
+        v2: old,
+        bridge_minter_allowances: LookupMap::new(b"q".to_vec()),
+        next_bridge_nonce: 1,
+        bridge_deposits: LookupSet::new(b"h".to_vec()),
+    });
+}
+
+/// v3 -> v4: holder index. The index starts empty: existing holders must be added with
+/// `backfill_holders` (see the `holders` module).
+fn migrate_v3_to_v4() {
+    let old: StateV3 = env::state_read().expect("Old state doesn't exist");
+    env::state_write(&StateV4 {
+        v3: old,
+        holders: UnorderedSet::new(b"i".to_vec()),
+        holders_backfill_complete: false,
+    });
+}
+
+/// v4 -> v5: supply accounting. The holder index is not changed: legacy vesting locks are
+/// recorded when the accounts are updated or with `refresh_vesting_locks`.
+fn migrate_v4_to_v5() {
+    let old: StateV4 = env::state_read().expect("Old state doesn't exist");
+    // mint and burn history before the migration is unknown
+    let total_minted = old.v3.v2.total_supply;
+    env::state_write(&StateV5 {
+        v4: old,
+        total_minted,
+        total_burned: 0,
+        // legacy vesting locks are recorded when accounts are updated or backfilled
+        vesting_locked: LookupMap::new(b"k".to_vec()),
+        total_vesting_locked: 0,
+        // minted amounts before the migration are unknown
+        minted_by_minter: UnorderedMap::new(b"t".to_vec()),
+        excluded_accounts: UnorderedSet::new(b"j".to_vec()),
+    });
+}
+
+/// v5 -> v6: account freezes.
+fn migrate_v5_to_v6() {
+    let old: StateV5 = env::state_read().expect("Old state doesn't exist");
+    env::state_write(&StateV6 {
+        v5: old,
+        frozen: LookupMap::new(b"z".to_vec()),
+    });
+}
+
+/// v6 -> v7: transfer fee.
+fn migrate_v6_to_v7() {
+    let old: StateV6 = env::state_read().expect("Old state doesn't exist");
+    env::state_write(&StateV7 {
+        v6: old,
+        transfer_fee: None,
+        fee_exempt: UnorderedSet::new(b"x".to_vec()),
+    });
 }
 
 #[near_bindgen]
 impl Contract {
     //-----------------
     //-- migration called after code upgrade
-    ///  For next version upgrades, change this function.
     //-- executed after upgrade to NEW CODE
     //-----------------
-    /// This fn WILL be called by this contract from `pub fn upgrade` (started from DAO)
-    /// Originally a **NOOP implementation. KEEP IT if you haven't changed contract state.**
-    /// If you have changed state, you need to implement migration from old state (keep the old struct with different name to deserialize it first).
-    ///
+    /// This fn WILL be called by this contract from `deploy_staged_upgrade`.
+    /// Applies all migration steps from the stored state version to `STATE_VERSION`.
+    /// It's a NOOP if the state layout didn't change.
     #[init(ignore_state)] //do not auto-load state before this function
     #[private]
     pub fn migrate() -> Self {
-        // read state with OLD struct
-        // uncomment when state migration is required on upgrade
-        //let old: migrations::MetaPoolPrevStateStruct = env::state_read().expect("Old state doesn't exist");
-        let old: OldState = env::state_read().expect("Old state doesn't exist");
-
-        // can only be called by this same contract (it's called from fn upgrade())
+        // can only be called by this same contract (it's called from deploy_staged_upgrade())
         assert_eq!(
             &env::predecessor_account_id(),
             &env::current_account_id(),
             "Can only be called by this contract"
         );
+        let mut version = read_state_version();
+        assert!(
+            version <= STATE_VERSION,
+            "state version {} is newer than the code version {}",
+            version,
+            STATE_VERSION
+        );
+        let migrated = version < STATE_VERSION;
+        while version < STATE_VERSION {
+            MIGRATIONS[(version - 1) as usize]();
+            version += 1;
+        }
+        write_state_version(STATE_VERSION);
+        let mut this: Contract = env::state_read().expect("Contract state doesn't exist");
+        if migrated {
+            // the layout of the stored records could change
+            this.measure_storage_usage();
+        }
+        this
+    }
 
-        // uncomment when state migration is required on upgrade
-        // Create the new contract state using the data from the old contract state.
-        // returns this struct that gets stored as contract state
-        return Self {
-            metadata: old.metadata,
-            accounts: old.accounts,
-            owner_id: old.owner_id,
-            minters: old.minters,
-            total_supply: old.total_supply,
-            vested: old.vested,
-        };
+    /// Returns the version of the contract state layout.
+    pub fn get_state_version(&self) -> u32 {
+        read_state_version()
     }
 }
@@ -0,0 +1,155 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, Balance};
+
+use crate::util::*;
+use crate::vesting::Timestamp;
+
+/// Minting limits of a single minter.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MinterAllowance {
+    /// amount of tokens the minter can still mint in total.
+    pub allowance: Balance,
+    /// total amount of tokens minted by the minter so far.
+    pub minted: Balance,
+    /// budget of `mint` airdrop campaigns reserved from the allowance and not minted yet.
+    pub reserved: Balance,
+    /// optional maximum amount of tokens the minter can mint within one period.
+    pub period_cap: Option<Balance>,
+    /// duration of the emission period in nanoseconds. Used only if `period_cap` is set.
+    pub period_duration: Timestamp,
+    /// the timestamp in nanoseconds when the current period started.
+    pub period_start: Timestamp,
+    /// amount of tokens minted in the current period.
+    pub period_minted: Balance,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct MinterAllowanceJSON {
+    pub allowance: U128String,
+    pub minted: U128String,
+    pub reserved: U128String,
+    pub period_cap: Option<U128String>,
+    pub period_duration: U64String,
+    /// amount the minter can mint right now, taking into account both the total
+    /// allowance and the period cap.
+    pub mintable_now: U128String,
+}
+
+impl MinterAllowance {
+    pub fn new(allowance: Balance) -> Self {
+        Self {
+            allowance,
+            minted: 0,
+            reserved: 0,
+            period_cap: None,
+            period_duration: 0,
+            period_start: 0,
+            period_minted: 0,
+        }
+    }
+
+    /// Sets (or removes when `period_cap` is None) the per period emission cap.
+    /// The current period is restarted.
+    pub fn set_period_cap(&mut self, period_cap: Option<Balance>, period_duration: Timestamp) {
+        if period_cap.is_some() {
+            assert!(period_duration > 0, "period duration must be > 0");
+        }
+        self.period_cap = period_cap;
+        self.period_duration = period_duration;
+        self.period_start = env::block_timestamp();
+        self.period_minted = 0;
+    }
+
+    /// Returns true if the current emission period already elapsed.
+    #[inline]
+    fn period_elapsed(&self, now: Timestamp) -> bool {
+        now >= self.period_start.saturating_add(self.period_duration)
+    }
+
+    /// Returns the amount the minter can mint in the current block.
+    pub fn mintable_now(&self) -> Balance {
+        match self.period_cap {
+            Some(cap) => {
+                let period_minted = if self.period_elapsed(env::block_timestamp()) {
+                    0
+                } else {
+                    self.period_minted
+                };
+                std::cmp::min(self.allowance, cap.saturating_sub(period_minted))
+            }
+            None => self.allowance,
+        }
+    }
+
+    /// Records a mint of `amount` tokens.
+    /// Panics if the total allowance or the period cap would be exceeded.
+    pub fn consume(&mut self, amount: Balance) {
+        self.take(amount);
+        self.minted += amount;
+    }
+
+    /// Reserves `amount` tokens of the allowance, to be minted later (by airdrop claims).
+    /// Panics if the total allowance or the period cap would be exceeded.
+    pub fn reserve(&mut self, amount: Balance) {
+        self.take(amount);
+        self.reserved += amount;
+    }
+
+    /// Records a mint of `amount` previously reserved tokens.
+    /// Panics if less than `amount` tokens are reserved (eg the reservation was dropped with
+    /// the revoked minter role).
+    pub fn mint_reserved(&mut self, amount: Balance) {
+        assert!(
+            amount <= self.reserved,
+            "minter reservation exceeded, reserved: {}",
+            self.reserved
+        );
+        self.reserved -= amount;
+        self.minted += amount;
+    }
+
+    /// Returns up to `amount` of not minted reserved tokens to the allowance.
+    /// Returns the restored amount: it's lower than `amount` if the reservation was made
+    /// in a previous allowance (the minter role was revoked and granted again).
+    pub fn release(&mut self, amount: Balance) -> Balance {
+        let restored = std::cmp::min(amount, self.reserved);
+        self.reserved -= restored;
+        self.allowance += restored;
+        restored
+    }
+
+    /// Deducts `amount` from the allowance and the current period.
+    fn take(&mut self, amount: Balance) {
+        assert!(
+            amount <= self.allowance,
+            "minter allowance exceeded, remaining allowance: {}",
+            self.allowance
+        );
+        if let Some(cap) = self.period_cap {
+            let now = env::block_timestamp();
+            if self.period_elapsed(now) {
+                self.period_start = now;
+                self.period_minted = 0;
+            }
+            assert!(
+                amount <= cap.saturating_sub(self.period_minted),
+                "minter period cap exceeded, remaining in the current period: {}",
+                cap.saturating_sub(self.period_minted)
+            );
+            self.period_minted += amount;
+        }
+        self.allowance -= amount;
+    }
+
+    pub fn to_json(&self) -> MinterAllowanceJSON {
+        MinterAllowanceJSON {
+            allowance: self.allowance.into(),
+            minted: self.minted.into(),
+            reserved: self.reserved.into(),
+            period_cap: self.period_cap.map(|c| c.into()),
+            period_duration: self.period_duration.into(),
+            mintable_now: self.mintable_now().into(),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Two-step ownership transfer: the owner proposes a new owner, who has to accept the
+//! ownership, optionally only after a delay.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+use crate::events::OwnershipChanged;
+use crate::roles::ALL_ROLES;
+use crate::util::*;
+use crate::vesting::Timestamp;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PendingOwner {
+    pub account_id: AccountId,
+    /// timestamp in nanoseconds since when the ownership can be accepted.
+    pub accept_after: Timestamp,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PendingOwnerJSON {
+    pub account_id: AccountId,
+    pub accept_after: U64String,
+}
+
+impl Contract {
+    /// Moves all roles explicitly granted to `from` to `to`. The minter allowances of `from`
+    /// replace the allowances of `to`.
+    fn internal_move_roles(&mut self, from: &AccountId, to: &AccountId) {
+        for role in ALL_ROLES.iter() {
+            if !self
+                .roles
+                .get(role)
+                .map_or(false, |members| members.contains(from))
+            {
+                continue;
+            }
+            let allowance = self.role_allowances(*role).and_then(|m| m.get(from));
+            self.internal_revoke_role(*role, from);
+            self.internal_grant_role(*role, to);
+            if let Some(a) = allowance {
+                self.role_allowances(*role).unwrap().insert(to, &a);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Proposes a new owner. The new owner must call `accept_ownership` to finish the
+    /// transfer, not earlier than `delay` nanoseconds from now.
+    /// Replaces the previous proposal.
+    #[payable]
+    pub fn propose_owner(&mut self, owner_id: ValidAccountId, delay: Option<U64String>) {
+        assert_one_yocto();
+        self.assert_owner();
+        let owner_id: AccountId = owner_id.into();
+        assert_ne!(owner_id, self.owner_id, "account is already the owner");
+        let accept_after = env::block_timestamp() + delay.map(|d| d.0).unwrap_or(0);
+        OwnershipChanged {
+            event: "ownership_transfer_proposed",
+            owner_id: &self.owner_id,
+            new_owner_id: &owner_id,
+            accept_after: Some(accept_after.into()),
+        }
+        .emit();
+        self.pending_owner = Some(PendingOwner {
+            account_id: owner_id,
+            accept_after,
+        });
+    }
+
+    /// Cancels the pending ownership transfer.
+    #[payable]
+    pub fn cancel_ownership_transfer(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        let p = self
+            .pending_owner
+            .take()
+            .expect("no pending ownership transfer");
+        OwnershipChanged {
+            event: "ownership_transfer_cancelled",
+            owner_id: &self.owner_id,
+            new_owner_id: &p.account_id,
+            accept_after: None,
+        }
+        .emit();
+    }
+
+    /// Accepts the ownership. Must be called by the proposed owner after the delay.
+    /// All roles of the previous owner (including its minter allowance) are moved to
+    /// the new owner.
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+        let p = self
+            .pending_owner
+            .take()
+            .expect("no pending ownership transfer");
+        assert_eq!(
+            env::predecessor_account_id(),
+            p.account_id,
+            "can only be called by the proposed owner"
+        );
+        assert!(
+            !self.internal_has_role(Role::Pauser, &p.account_id),
+            "guardian must be distinct from the owner, renounce the pauser role first"
+        );
+        assert!(
+            env::block_timestamp() >= p.accept_after,
+            "ownership can't be accepted before {}",
+            p.accept_after
+        );
+        OwnershipChanged {
+            event: "ownership_transferred",
+            owner_id: &self.owner_id,
+            new_owner_id: &p.account_id,
+            accept_after: None,
+        }
+        .emit();
+        let previous = std::mem::replace(&mut self.owner_id, p.account_id.clone());
+        self.internal_move_roles(&previous, &p.account_id);
+    }
+
+    /// Returns the proposed owner, if any.
+    pub fn get_pending_owner(&self) -> Option<PendingOwnerJSON> {
+        self.pending_owner.as_ref().map(|p| PendingOwnerJSON {
+            account_id: p.account_id.clone(),
+            accept_after: p.accept_after.into(),
+        })
+    }
+}
@@ -0,0 +1,115 @@
+//! Token wide and per feature pause, controlled by guardians (accounts with the `Pauser` role).
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen};
+
+use crate::events::PauseChanged;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseFeature {
+    /// pauses minting, transfers and burning.
+    All,
+    Mint,
+    Transfer,
+    Burn,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, Default)]
+pub struct PauseStatus {
+    pub all: bool,
+    pub mint: bool,
+    pub transfer: bool,
+    pub burn: bool,
+}
+
+impl PauseStatus {
+    fn flag(&mut self, feature: PauseFeature) -> &mut bool {
+        match feature {
+            PauseFeature::All => &mut self.all,
+            PauseFeature::Mint => &mut self.mint,
+            PauseFeature::Transfer => &mut self.transfer,
+            PauseFeature::Burn => &mut self.burn,
+        }
+    }
+
+    /// Returns true if the feature is paused directly or by the token wide pause.
+    pub fn is_paused(&self, feature: PauseFeature) -> bool {
+        self.all
+            || match feature {
+                PauseFeature::All => false,
+                PauseFeature::Mint => self.mint,
+                PauseFeature::Transfer => self.transfer,
+                PauseFeature::Burn => self.burn,
+            }
+    }
+}
+
+impl Contract {
+    /// Panics if the feature is paused.
+    pub(crate) fn assert_not_paused(&self, feature: PauseFeature) {
+        assert!(
+            !self.paused.is_paused(feature),
+            "{} is paused",
+            feature.as_str()
+        );
+    }
+
+    fn internal_set_paused(&mut self, feature: PauseFeature, paused: bool) {
+        assert_one_yocto();
+        self.assert_role(Role::Pauser);
+        let flag = self.paused.flag(feature);
+        assert!(
+            *flag != paused,
+            "{} is already {}",
+            feature.as_str(),
+            if paused { "paused" } else { "unpaused" }
+        );
+        *flag = paused;
+        PauseChanged {
+            feature,
+            paused,
+            account_id: &env::predecessor_account_id(),
+        }
+        .emit();
+    }
+}
+
+impl PauseFeature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PauseFeature::All => "token",
+            PauseFeature::Mint => "minting",
+            PauseFeature::Transfer => "transfer",
+            PauseFeature::Burn => "burning",
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Pauses the whole token (`all`) or a single feature. Only guardians can call this
+    /// function.
+    #[payable]
+    pub fn pause(&mut self, feature: PauseFeature) {
+        self.internal_set_paused(feature, true);
+    }
+
+    /// Unpauses the whole token (`all`) or a single feature. Only guardians can call this
+    /// function. NOTE: unpausing `all` doesn't unpause features paused separately.
+    #[payable]
+    pub fn unpause(&mut self, feature: PauseFeature) {
+        self.internal_set_paused(feature, false);
+    }
+
+    /// Returns true if the feature is paused (directly or by the token wide pause).
+    pub fn is_paused(&self, feature: PauseFeature) -> bool {
+        self.paused.is_paused(feature)
+    }
+
+    pub fn get_pause_status(&self) -> PauseStatus {
+        PauseStatus { ..self.paused }
+    }
+}
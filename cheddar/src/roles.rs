@@ -0,0 +1,192 @@
+//! Role based access control.
+//! The owner implicitly has the `Admin` role. Admins can grant and revoke all roles.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId};
+
+use crate::events::RoleChanged;
+use crate::minters::MinterAllowance;
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// can grant and revoke roles and manage minters and the max supply.
+    Admin,
+    /// can mint tokens (limited by the minter allowance).
+    Minter,
+    /// can cancel vesting grants.
+    VestingManager,
+    /// can update the token metadata.
+    MetadataManager,
+    /// guardian: can pause and unpause the token. Must be distinct from the owner.
+    Pauser,
+    /// can upgrade the contract.
+    Upgrader,
+    /// can mint tokens deposited through the bridge (see `bridge_mint`).
+    BridgeMinter,
+}
+
+pub const ALL_ROLES: [Role; 7] = [
+    Role::Admin,
+    Role::Minter,
+    Role::VestingManager,
+    Role::MetadataManager,
+    Role::Pauser,
+    Role::Upgrader,
+    Role::BridgeMinter,
+];
+
+impl Contract {
+    /// Returns true if the account has the role. The owner always has the `Admin` role.
+    pub(crate) fn internal_has_role(&self, role: Role, account_id: &AccountId) -> bool {
+        if role == Role::Admin && *account_id == self.owner_id {
+            return true;
+        }
+        match self.roles.get(&role) {
+            Some(members) => members.contains(account_id),
+            None => false,
+        }
+    }
+
+    /// Panics if the predecessor doesn't have the role.
+    pub(crate) fn assert_role(&self, role: Role) {
+        assert!(
+            self.internal_has_role(role, &env::predecessor_account_id()),
+            "missing role: {}",
+            role.as_str()
+        );
+    }
+
+    /// Returns the mint allowances of the role members: minters and bridge minters have
+    /// separate allowances.
+    pub(crate) fn role_allowances(
+        &mut self,
+        role: Role,
+    ) -> Option<&mut LookupMap<AccountId, MinterAllowance>> {
+        match role {
+            Role::Minter => Some(&mut self.minter_allowances),
+            Role::BridgeMinter => Some(&mut self.bridge_minter_allowances),
+            _ => None,
+        }
+    }
+
+    /// Grants the role. Returns false if the account already has the role.
+    pub(crate) fn internal_grant_role(&mut self, role: Role, account_id: &AccountId) -> bool {
+        assert!(
+            role != Role::Pauser || *account_id != self.owner_id,
+            "guardian must be distinct from the owner"
+        );
+        let mut members = self.roles.get(&role).unwrap_or_default();
+        if members.contains(account_id) {
+            return false;
+        }
+        members.push(account_id.clone());
+        self.roles.insert(&role, &members);
+        if let Some(allowances) = self.role_allowances(role) {
+            if !allowances.contains_key(account_id) {
+                allowances.insert(account_id, &MinterAllowance::new(0));
+            }
+        }
+        RoleChanged {
+            role,
+            account_id,
+            granted: true,
+        }
+        .emit();
+        true
+    }
+
+    /// Revokes the role. Returns false if the account doesn't have the role.
+    pub(crate) fn internal_revoke_role(&mut self, role: Role, account_id: &AccountId) -> bool {
+        let mut members = self.roles.get(&role).unwrap_or_default();
+        match members.iter().position(|x| x == account_id) {
+            Some(inx) => {
+                members.swap_remove(inx);
+            }
+            None => return false,
+        }
+        self.roles.insert(&role, &members);
+        if let Some(allowances) = self.role_allowances(role) {
+            allowances.remove(account_id);
+        }
+        RoleChanged {
+            role,
+            account_id,
+            granted: false,
+        }
+        .emit();
+        true
+    }
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Minter => "minter",
+            Role::VestingManager => "vesting_manager",
+            Role::MetadataManager => "metadata_manager",
+            Role::Pauser => "pauser",
+            Role::Upgrader => "upgrader",
+            Role::BridgeMinter => "bridge_minter",
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Grants the role to the account. Only admins can call this function.
+    #[payable]
+    pub fn grant_role(&mut self, role: Role, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            self.internal_grant_role(role, account_id.as_ref()),
+            "account already has the role"
+        );
+    }
+
+    /// Revokes the role from the account. Only admins can call this function.
+    #[payable]
+    pub fn revoke_role(&mut self, role: Role, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            self.internal_revoke_role(role, account_id.as_ref()),
+            "account doesn't have the role"
+        );
+    }
+
+    /// Removes the role from the caller.
+    #[payable]
+    pub fn renounce_role(&mut self, role: Role) {
+        assert_one_yocto();
+        assert!(
+            self.internal_revoke_role(role, &env::predecessor_account_id()),
+            "account doesn't have the role"
+        );
+    }
+
+    pub fn has_role(&self, role: Role, account_id: ValidAccountId) -> bool {
+        self.internal_has_role(role, account_id.as_ref())
+    }
+
+    /// Returns all accounts with the given role. The owner is not listed as an admin,
+    /// unless the `Admin` role was explicitly granted to it.
+    pub fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.roles.get(&role).unwrap_or_default()
+    }
+
+    /// Returns all roles of the account.
+    pub fn get_account_roles(&self, account_id: ValidAccountId) -> Vec<Role> {
+        ALL_ROLES
+            .iter()
+            .filter(|r| self.internal_has_role(**r, account_id.as_ref()))
+            .cloned()
+            .collect()
+    }
+}
@@ -1,14 +1,14 @@
+use crate::checkpoints::{account_history_prefix, remove_history};
 use crate::*;
 use near_contract_standards::storage_management::{
     StorageBalance, StorageBalanceBounds, StorageManagement,
 };
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::{ValidAccountId, U128};
-use near_sdk::{assert_one_yocto, env, log, near_bindgen, AccountId, Balance, Promise};
-
-// The storage size in bytes for one account.
-// 2*16 (two u128) + 64 (acc id)
-const ACCOUNT_STORAGE: u128 = 3 * 16 + 64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, Promise, StorageUsage,
+};
 
 /// AccBalance is a record of user near and token holding. Near holding is used
 /// to cover storage cost.
@@ -18,25 +18,72 @@ pub struct AccBalance {
     pub token: Balance,
 }
 
+/// Contract storage usage and NEAR budget report.
+#[derive(Deserialize, Serialize)]
+pub struct StorageReport {
+    /// measured storage (in bytes) of a single account registration.
+    pub account_storage_usage: StorageUsage,
+    /// total contract storage usage in bytes.
+    pub storage_usage: StorageUsage,
+    /// bytes used by registrations paid by the sponsor pool or by the contract.
+    pub sponsored_storage: StorageUsage,
+    /// bytes used by registrations paid by users with `storage_deposit`.
+    pub user_paid_storage: StorageUsage,
+    /// NEAR available to sponsor new registrations.
+    pub sponsor_pool: U128,
+    /// NEAR locked to cover the whole contract storage.
+    pub storage_locked: U128,
+    /// contract NEAR balance.
+    pub account_balance: U128,
+    /// NEAR which is neither locked for storage nor reserved in the sponsor pool.
+    pub headroom: U128,
+}
+
 impl Contract {
+    /// Measures the storage used by a single account registration, a single allowance and
+    /// an account history, using the longest possible account ids.
+    pub(crate) fn measure_storage_usage(&mut self) {
+        let initial = env::storage_usage();
+        let tmp_account_id = "a".repeat(64);
+        self.accounts
+            .insert(&tmp_account_id, &AccBalance { near: 0, token: 0 });
+        self.account_storage_usage = env::storage_usage() - initial;
+        self.accounts.remove(&tmp_account_id);
+
+        let initial = env::storage_usage();
+        let key = (tmp_account_id, "b".repeat(64));
+        self.allowances.insert(
+            &key,
+            &Allowance {
+                amount: 0,
+                expires_at: Some(0),
+                deposit: 0,
+            },
+        );
+        self.allowance_storage_usage = env::storage_usage() - initial;
+        self.allowances.remove(&key);
+
+        let (tmp_account_id, _) = key;
+        let initial = env::storage_usage();
+        let mut h = History::new(account_history_prefix(b"C", &tmp_account_id), 1, 0);
+        self.balance_checkpoints.insert(&tmp_account_id, &h);
+        self.history_storage_usage = env::storage_usage() - initial;
+        let initial = env::storage_usage();
+        h.push(0);
+        self.checkpoint_storage_usage = env::storage_usage() - initial;
+        remove_history(&mut self.balance_checkpoints, &tmp_account_id);
+    }
+
     /// Registers an account and panics if the account was already registered.
     pub(crate) fn register_account(&mut self, account_id: &AccountId, deposit: Balance) {
-        if self
-            .accounts
-            .insert(
-                account_id,
-                &AccBalance {
-                    near: deposit,
-                    token: 0,
-                },
-            )
-            .is_some()
-        {
+        if self.accounts.contains_key(account_id) {
             env::panic("The account is already registered".as_bytes());
         }
+        self.internal_insert_account(account_id, deposit);
     }
 
     /// It's like `register_account` but doesn't panic if the account already exists.
+    /// New accounts registered with 0 deposit are sponsored.
     #[inline]
     pub(crate) fn try_register_account(
         &mut self,
@@ -46,39 +93,130 @@ impl Contract {
         if let Some(a) = self.accounts.get(account_id) {
             return a;
         }
+        self.internal_insert_account(account_id, deposit)
+    }
+
+    /// Inserts a new account and records its storage usage. If `deposit` is 0, the
+    /// registration is sponsored: the storage cost is covered by the sponsor pool
+    /// (or by the contract balance if the pool is empty).
+    fn internal_insert_account(&mut self, account_id: &AccountId, deposit: Balance) -> AccBalance {
+        let initial = env::storage_usage();
         let a = AccBalance {
             near: deposit,
             token: 0,
         };
         self.accounts.insert(account_id, &a);
-        return a;
+        let used = env::storage_usage() - initial;
+        if deposit == 0 {
+            self.sponsored_storage += used;
+            let cost = used as Balance * env::storage_byte_cost();
+            let paid = std::cmp::min(cost, self.sponsor_pool);
+            if paid < cost {
+                log!("sponsor pool is empty, account storage is paid by the contract");
+            }
+            if paid > 0 {
+                self.sponsor_pool -= paid;
+                self.sponsored_accounts.insert(account_id, &paid);
+            }
+        } else {
+            self.user_paid_storage += used;
+        }
+        a
+    }
+
+    /// Removes the account record and updates the storage accounting. The amount the sponsor
+    /// pool paid for the account registration is returned to the pool.
+    fn internal_remove_account(&mut self, account_id: &AccountId, a: &AccBalance) {
+        let initial = env::storage_usage();
+        self.accounts.remove(account_id);
+        let released = initial - env::storage_usage();
+        // accounts registered before the storage accounting was introduced are not counted.
+        if a.near == 0 {
+            self.sponsored_storage = self.sponsored_storage.saturating_sub(released);
+            if let Some(paid) = self.sponsored_accounts.remove(account_id) {
+                self.sponsor_pool += paid;
+            }
+        } else {
+            self.user_paid_storage = self.user_paid_storage.saturating_sub(released);
+        }
+    }
+
+    /// NEAR deposit required to store a new allowance.
+    pub(crate) fn allowance_storage_cost(&self) -> Balance {
+        self.allowance_storage_usage as Balance * env::storage_byte_cost()
     }
 
-    /// Internal method that returns the Account ID and the balance in case the account was
-    /// registered.
-    fn internal_storage_unregister(&mut self, force: Option<bool>) -> Option<(AccountId, Balance)> {
+    /// NEAR deposit required to register an account.
+    pub(crate) fn storage_cost(&self) -> Balance {
+        self.account_storage_usage as Balance * env::storage_byte_cost()
+    }
+
+    fn storage_balance(&self) -> StorageBalance {
+        StorageBalance {
+            total: U128::from(self.storage_cost()),
+            available: 0.into(),
+        }
+    }
+
+    /// Closes the caller account: the remaining token balance is transferred to the
+    /// `receiver_id` or, if `receiver_id` is None and `force` is true, burned.
+    /// The NEAR storage deposit is refunded if it was paid by the user; storage of sponsored
+    /// accounts is returned to the sponsor pool. The balance and vote histories are removed
+    /// and their deposits refunded.
+    /// Panics if the account has tokens locked in vesting, is frozen or is the fee treasury.
+    /// Returns false if the account is not registered.
+    pub(crate) fn internal_close_account(
+        &mut self,
+        receiver_id: Option<AccountId>,
+        force: bool,
+    ) -> bool {
         assert_one_yocto();
         let account_id = env::predecessor_account_id();
-        let force = force.unwrap_or(false);
-        if let Some(balance) = self.accounts.get(&account_id) {
-            if balance.token == 0 || force {
-                self.accounts.remove(&account_id);
-                if balance.token != 0 {
-                    self.total_supply -= balance.token;
-                    // we add 1 because the function requires 1 yocto payment
-                    Promise::new(account_id.clone()).transfer(balance.near + 1);
+        if !self.accounts.contains_key(&account_id) {
+            log!("The account {} is not registered", &account_id);
+            return false;
+        }
+        self.assert_not_frozen(&account_id);
+        assert!(
+            self.fee_treasury() != Some(&account_id),
+            "Can't unregister the transfer fee treasury"
+        );
+        // fully vested grants are removed, so no vesting records are left after this check.
+        assert!(
+            self.internal_update_locked(&account_id) == 0,
+            "Can't unregister the account with locked vesting tokens"
+        );
+        let balance = self._balance_of(&account_id);
+        if balance > 0 {
+            match receiver_id {
+                Some(receiver_id) => self.internal_transfer_without_fee(
+                    &account_id,
+                    &receiver_id,
+                    balance,
+                    "account closure",
+                ),
+                None if force => {
+                    self.internal_burn(&account_id, balance, Some("force storage unregister"))
                 }
-                Some((account_id, balance.near))
-            } else {
-                env::panic(
+                None => env::panic(
                     "Can't unregister the account with the positive balance without force"
                         .as_bytes(),
-                )
+                ),
             }
-        } else {
-            log!("The account {} is not registered", &account_id);
-            None
         }
+        // delegated votes were already moved when the balance was set to 0.
+        self.internal_delegate(&account_id, None, false);
+        self.holders.remove(&account_id);
+        let history_deposit = remove_history(&mut self.balance_checkpoints, &account_id)
+            + remove_history(&mut self.vote_checkpoints, &account_id);
+        let a = self._must_balance_of(&account_id);
+        self.internal_remove_account(&account_id, &a);
+        let refund = a.near + history_deposit;
+        if refund > 0 {
+            // we add 1 because the function requires 1 yocto payment
+            Promise::new(account_id).transfer(refund + 1);
+        }
+        true
     }
 }
 
@@ -109,7 +247,7 @@ impl StorageManagement for Contract {
                 Promise::new(env::predecessor_account_id()).transfer(amount);
             }
         } else {
-            let cost = storage_cost();
+            let cost = self.storage_cost();
             assert!(
                 amount >= cost,
                 "attached deposit: {},  required: {}",
@@ -122,7 +260,7 @@ impl StorageManagement for Contract {
                 Promise::new(env::predecessor_account_id()).transfer(refund);
             }
         }
-        return storage_balance();
+        return self.storage_balance();
     }
 
     /// While storage_withdraw normally allows the caller to retrieve `available` balance, the basic
@@ -141,7 +279,7 @@ impl StorageManagement for Contract {
                         "The amount is greater than the available storage balance".as_bytes(),
                     );
                 }
-                _ => storage_balance(),
+                _ => self.storage_balance(),
             }
         } else {
             env::panic(
@@ -150,12 +288,14 @@ impl StorageManagement for Contract {
         }
     }
 
+    /// Closes the caller account, see `close_account`. With `force` the remaining
+    /// token balance is burned.
     fn storage_unregister(&mut self, force: Option<bool>) -> bool {
-        self.internal_storage_unregister(force).is_some()
+        self.internal_close_account(None, force.unwrap_or(false))
     }
 
     fn storage_balance_bounds(&self) -> StorageBalanceBounds {
-        let d = U128::from(storage_cost());
+        let d = U128::from(self.storage_cost());
         StorageBalanceBounds {
             min: d,
             max: Some(d),
@@ -164,20 +304,76 @@ impl StorageManagement for Contract {
 
     fn storage_balance_of(&self, account_id: ValidAccountId) -> Option<StorageBalance> {
         if self.accounts.contains_key(account_id.as_ref()) {
-            Some(storage_balance())
+            Some(self.storage_balance())
         } else {
             None
         }
     }
 }
 
-fn storage_balance() -> StorageBalance {
-    StorageBalance {
-        total: U128::from(storage_cost()),
-        available: 0.into(),
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached deposit to the sponsor pool, which pays for the storage of
+    /// accounts registered for free (eg by minting or farming).
+    #[payable]
+    pub fn fund_sponsor_pool(&mut self) -> U128 {
+        assert!(
+            env::attached_deposit() > 0,
+            "attached deposit must be positive"
+        );
+        self.sponsor_pool += env::attached_deposit();
+        self.sponsor_pool.into()
+    }
+
+    /// Withdraws `amount` of NEAR from the sponsor pool to the caller.
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn withdraw_sponsor_pool(&mut self, amount: U128) -> U128 {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            amount.0 <= self.sponsor_pool,
+            "not enough NEAR in the sponsor pool: {}",
+            self.sponsor_pool
+        );
+        self.sponsor_pool -= amount.0;
+        Promise::new(env::predecessor_account_id()).transfer(amount.0);
+        self.sponsor_pool.into()
     }
-}
 
-fn storage_cost() -> u128 {
-    ACCOUNT_STORAGE * env::storage_byte_cost()
+    /// Closes the caller account and transfers the remaining token balance to the
+    /// `receiver_id` (which must be registered). If `receiver_id` is not provided, the account
+    /// must have 0 balance, unless `force` is true, in which case the balance is burned.
+    /// The NEAR storage deposit is refunded if it was paid by the user, together with the
+    /// balance and vote history deposits.
+    /// Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn close_account(
+        &mut self,
+        receiver_id: Option<ValidAccountId>,
+        force: Option<bool>,
+    ) -> bool {
+        self.internal_close_account(receiver_id.map(|r| r.into()), force.unwrap_or(false))
+    }
+
+    /// Returns the contract storage usage, how much of it is sponsored vs paid by users,
+    /// and how much NEAR is still available.
+    pub fn get_storage_report(&self) -> StorageReport {
+        let storage_usage = env::storage_usage();
+        let storage_locked = storage_usage as Balance * env::storage_byte_cost();
+        let account_balance = env::account_balance();
+        StorageReport {
+            account_storage_usage: self.account_storage_usage,
+            storage_usage,
+            sponsored_storage: self.sponsored_storage,
+            user_paid_storage: self.user_paid_storage,
+            sponsor_pool: self.sponsor_pool.into(),
+            storage_locked: storage_locked.into(),
+            account_balance: account_balance.into(),
+            headroom: account_balance
+                .saturating_sub(storage_locked)
+                .saturating_sub(self.sponsor_pool)
+                .into(),
+        }
+    }
 }
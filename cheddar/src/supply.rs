@@ -0,0 +1,174 @@
+//! Supply accounting: vesting locks, burned and minted totals and the set of excluded
+//! (treasury) accounts, used to compute the circulating supply.
+//!
+//! The vesting lock of an account is recorded in `vesting_locked` whenever its vesting is
+//! updated (any balance reducing operation, new or cancelled grants), and the sum of the
+//! recorded locks is kept in `total_vesting_locked`, so the supply views don't iterate over
+//! accounts. Tokens vested since the last update are still counted as locked: use
+//! `refresh_vesting_locks` to update the recorded locks. Accounts with legacy vesting grants
+//! (from before the migration) are recorded by the holder backfill (see the `holders`
+//! module): the supply breakdown reports `backfill_complete` = false until it's finished.
+//! Tokens of the excluded accounts are subtracted once: vesting of the excluded accounts is
+//! already part of their balance, so it's not included in `total_vesting_locked`.
+
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, near_bindgen, AccountId, Balance};
+
+use crate::holders::HolderJSON;
+use crate::util::*;
+use crate::*;
+
+/// Maximum number of excluded accounts.
+pub const MAX_EXCLUDED_ACCOUNTS: u64 = 20;
+
+#[derive(Deserialize, Serialize)]
+pub struct MinterSupplyJSON {
+    pub account_id: AccountId,
+    pub minted: U128String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SupplyBreakdown {
+    pub total_supply: U128String,
+    pub max_supply: U128String,
+    pub total_minted: U128String,
+    pub total_burned: U128String,
+    /// recorded vesting locks of not excluded accounts.
+    pub vesting_locked: U128String,
+    /// total balance of the excluded accounts.
+    pub excluded: U128String,
+    pub excluded_accounts: Vec<HolderJSON>,
+    /// tokens minted by each minter and bridge minter, including former ones.
+    pub minted_by_minter: Vec<MinterSupplyJSON>,
+    pub circulating_supply: U128String,
+    /// false if the contract was migrated and the holder backfill is not finished yet:
+    /// legacy vesting locks may be missing.
+    pub backfill_complete: bool,
+}
+
+impl Contract {
+    /// Records the current vesting lock of the account and updates the account delegated
+    /// votes.
+    pub(crate) fn internal_record_vesting_locked(
+        &mut self,
+        account_id: &AccountId,
+        locked: Balance,
+    ) {
+        let prev = self.vesting_locked.get(account_id).unwrap_or(0);
+        if prev == locked {
+            return;
+        }
+        if locked == 0 {
+            self.vesting_locked.remove(account_id);
+        } else {
+            self.vesting_locked.insert(account_id, &locked);
+        }
+        if !self.excluded_accounts.contains(account_id) {
+            self.total_vesting_locked = self.total_vesting_locked - prev + locked;
+        }
+        self.internal_refresh_delegated_votes(account_id);
+    }
+
+    /// Records `amount` minted by the minter (or bridge minter).
+    pub(crate) fn internal_record_minted(&mut self, minter_id: &AccountId, amount: Balance) {
+        let minted = self.minted_by_minter.get(minter_id).unwrap_or(0);
+        self.minted_by_minter.insert(minter_id, &(minted + amount));
+    }
+
+    fn internal_excluded_balance(&self) -> Balance {
+        self.excluded_accounts
+            .iter()
+            .map(|a| self._balance_of(&a))
+            .sum()
+    }
+
+    fn internal_circulating_supply(&self) -> Balance {
+        self.total_supply
+            .saturating_sub(self.total_vesting_locked)
+            .saturating_sub(self.internal_excluded_balance())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the circulating supply: total supply minus tokens locked in vesting and
+    /// the balance of the excluded accounts.
+    pub fn ft_circulating_supply(&self) -> U128String {
+        self.internal_circulating_supply().into()
+    }
+
+    pub fn get_supply_breakdown(&self) -> SupplyBreakdown {
+        let minted_by_minter = self
+            .minted_by_minter
+            .iter()
+            .map(|(account_id, minted)| MinterSupplyJSON {
+                account_id,
+                minted: minted.into(),
+            })
+            .collect();
+        SupplyBreakdown {
+            total_supply: self.total_supply.into(),
+            max_supply: self.max_supply.into(),
+            total_minted: self.total_minted.into(),
+            total_burned: self.total_burned.into(),
+            vesting_locked: self.total_vesting_locked.into(),
+            excluded: self.internal_excluded_balance().into(),
+            excluded_accounts: self
+                .excluded_accounts
+                .iter()
+                .map(|a| HolderJSON {
+                    balance: self._balance_of(&a).into(),
+                    account_id: a,
+                })
+                .collect(),
+            minted_by_minter,
+            circulating_supply: self.internal_circulating_supply().into(),
+            backfill_complete: self.holders_backfill_complete,
+        }
+    }
+
+    /// Updates the recorded vesting locks of the accounts (eg after grants vested), which
+    /// also updates their delegated votes and removes fully vested grants.
+    /// Returns the total recorded vesting lock of not excluded accounts.
+    pub fn refresh_vesting_locks(&mut self, account_ids: Vec<ValidAccountId>) -> U128String {
+        for a in account_ids.iter() {
+            self.internal_update_locked(a.as_ref());
+        }
+        self.total_vesting_locked.into()
+    }
+
+    /// Excludes the account (eg treasury) from the circulating supply.
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn add_excluded_account(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            self.excluded_accounts.len() < MAX_EXCLUDED_ACCOUNTS,
+            "too many excluded accounts, max: {}",
+            MAX_EXCLUDED_ACCOUNTS
+        );
+        assert!(
+            self.excluded_accounts.insert(account_id.as_ref()),
+            "account is already excluded"
+        );
+        self.total_vesting_locked -= self.vesting_locked.get(account_id.as_ref()).unwrap_or(0);
+    }
+
+    /// Only admins can call this function. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn remove_excluded_account(&mut self, account_id: ValidAccountId) {
+        assert_one_yocto();
+        self.assert_role(Role::Admin);
+        assert!(
+            self.excluded_accounts.remove(account_id.as_ref()),
+            "account is not excluded"
+        );
+        self.total_vesting_locked += self.vesting_locked.get(account_id.as_ref()).unwrap_or(0);
+    }
+
+    pub fn get_excluded_accounts(&self) -> Vec<AccountId> {
+        self.excluded_accounts.to_vec()
+    }
+}
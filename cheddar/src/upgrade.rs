@@ -1,59 +1,180 @@
 //! Implement all the relevant logic for smart contract upgrade.
+//! Upgrades are staged and timelocked: an upgrader stages the new code with `stage_upgrade`,
+//! and only after `UPGRADE_DELAY` the code can be deployed with `deploy_staged_upgrade`.
+//! In the meantime anyone can inspect the staged code hash. Admins and upgraders can cancel
+//! the staged upgrade.
 
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, AccountId, Balance, Gas, Promise};
+
+use crate::events::UpgradeEvent;
+use crate::util::*;
+use crate::vesting::Timestamp;
 use crate::*;
 
-#[cfg(target_arch = "wasm32")]
-mod upgrade {
-    use near_sdk::env::BLOCKCHAIN_INTERFACE;
-    use near_sdk::Gas;
+/// Storage key of the staged contract code.
+const STAGED_CODE_KEY: &[u8] = b"u";
+/// Minimum time (in nanoseconds) between staging and deploying an upgrade: 2 days.
+pub const UPGRADE_DELAY: Timestamp = 2 * 24 * 3600 * 1_000_000_000;
+/// Gas reserved to finish the `deploy_staged_upgrade` call, the rest is attached
+/// to the `migrate` call.
+const GAS_FOR_DEPLOY_CALL: Gas = 20 * TGAS;
 
-    use super::*;
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StagedUpgrade {
+    /// sha256 of the staged code.
+    pub code_hash: Vec<u8>,
+    /// timestamp in nanoseconds since when the code can be deployed.
+    pub deploy_after: Timestamp,
+    /// account which staged the upgrade and paid for the code storage.
+    pub staged_by: AccountId,
+    /// NEAR deposited to cover the code storage, refunded when the code is removed.
+    pub deposit: Balance,
+}
 
-    const BLOCKCHAIN_INTERFACE_NOT_SET_ERR: &str = "Blockchain interface not set.";
+#[derive(Deserialize, Serialize)]
+pub struct StagedUpgradeJSON {
+    /// hex encoded sha256 of the staged code.
+    pub code_hash: String,
+    pub deploy_after: U64String,
+    pub staged_by: AccountId,
+}
 
-    /// Gas for calling migration call.
-    pub const GAS_FOR_MIGRATE_CALL: Gas = 5_000_000_000_000;
+impl Contract {
+    /// Stores the code of the upgrade. The caller must be an upgrader and attach a deposit
+    /// to cover the code storage. Unused deposit is refunded.
+    pub(crate) fn internal_stage_upgrade(&mut self, code: &[u8]) {
+        self.assert_role(Role::Upgrader);
+        assert!(!code.is_empty(), "code can't be empty");
+        assert!(
+            self.staged_upgrade.is_none(),
+            "an upgrade is already staged, cancel it first"
+        );
+        let initial = env::storage_usage();
+        env::storage_write(STAGED_CODE_KEY, code);
+        let cost = (env::storage_usage() - initial) as Balance * env::storage_byte_cost();
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= cost,
+            "attached deposit: {},  required: {}",
+            deposit,
+            cost
+        );
+        let staged_by = env::predecessor_account_id();
+        if deposit > cost {
+            Promise::new(staged_by.clone()).transfer(deposit - cost);
+        }
+        let u = StagedUpgrade {
+            code_hash: env::sha256(code),
+            deploy_after: env::block_timestamp() + UPGRADE_DELAY,
+            staged_by,
+            deposit: cost,
+        };
+        UpgradeEvent {
+            event: "upgrade_staged",
+            code_hash: to_hex(&u.code_hash),
+            deploy_after: Some(u.deploy_after.into()),
+        }
+        .emit();
+        self.staged_upgrade = Some(u);
+    }
 
-    /// Self upgrade and call migrate, optimizes gas by not loading into memory the code.
-    /// Takes as input non serialized set of bytes of the code.
+    /// Removes the staged code and refunds the storage deposit.
+    fn internal_remove_staged_upgrade(&mut self) -> StagedUpgrade {
+        let u = self.staged_upgrade.take().expect("no staged upgrade");
+        env::storage_remove(STAGED_CODE_KEY);
+        if u.deposit > 0 {
+            Promise::new(u.staged_by.clone()).transfer(u.deposit);
+        }
+        u
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deploys the staged code and calls `migrate` on the new code.
+    /// Can be called by an upgrader after the upgrade delay. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn deploy_staged_upgrade(&mut self) -> Promise {
+        assert_one_yocto();
+        self.assert_role(Role::Upgrader);
+        let deploy_after = self
+            .staged_upgrade
+            .as_ref()
+            .expect("no staged upgrade")
+            .deploy_after;
+        assert!(
+            env::block_timestamp() >= deploy_after,
+            "staged upgrade can't be deployed before {}",
+            deploy_after
+        );
+        let code = env::storage_read(STAGED_CODE_KEY).expect("staged code not found");
+        let u = self.internal_remove_staged_upgrade();
+        UpgradeEvent {
+            event: "upgrade_deployed",
+            code_hash: to_hex(&u.code_hash),
+            deploy_after: None,
+        }
+        .emit();
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(
+                b"migrate".to_vec(),
+                vec![],
+                NO_DEPOSIT,
+                env::prepaid_gas() - env::used_gas() - GAS_FOR_DEPLOY_CALL,
+            )
+    }
+
+    /// Cancels the staged upgrade and refunds the code storage deposit.
+    /// Can be called by an admin or an upgrader. Requires 1 yoctoNEAR.
+    #[payable]
+    pub fn cancel_staged_upgrade(&mut self) {
+        assert_one_yocto();
+        let caller = env::predecessor_account_id();
+        assert!(
+            self.internal_has_role(Role::Admin, &caller)
+                || self.internal_has_role(Role::Upgrader, &caller),
+            "only admins and upgraders can cancel the upgrade"
+        );
+        let u = self.internal_remove_staged_upgrade();
+        UpgradeEvent {
+            event: "upgrade_cancelled",
+            code_hash: to_hex(&u.code_hash),
+            deploy_after: None,
+        }
+        .emit();
+    }
+
+    /// Returns the hex encoded sha256 hash of the staged code.
+    pub fn get_staged_code_hash(&self) -> Option<String> {
+        self.staged_upgrade.as_ref().map(|u| to_hex(&u.code_hash))
+    }
+
+    pub fn get_staged_upgrade(&self) -> Option<StagedUpgradeJSON> {
+        self.staged_upgrade.as_ref().map(|u| StagedUpgradeJSON {
+            code_hash: to_hex(&u.code_hash),
+            deploy_after: u.deploy_after.into(),
+            staged_by: u.staged_by.clone(),
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod upgrade {
+    use super::*;
+
+    /// Stages the upgrade. Takes as input non serialized set of bytes of the code, which
+    /// optimizes gas by not deserializing the code from JSON.
+    /// Requires a deposit to cover the code storage.
     #[no_mangle]
-    pub extern "C" fn upgrade() {
+    pub extern "C" fn stage_upgrade() {
         env::setup_panic_hook();
         env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
-        let contract: Contract = env::state_read().expect("ERR_CONTRACT_IS_NOT_INITIALIZED");
-        contract.assert_owner();
-        let current_id = env::current_account_id().into_bytes();
-        let method_name = "migrate".as_bytes().to_vec();
-        unsafe {
-            BLOCKCHAIN_INTERFACE.with(|b| {
-                // Load input into register 0.
-                b.borrow()
-                    .as_ref()
-                    .expect(BLOCKCHAIN_INTERFACE_NOT_SET_ERR)
-                    .input(0);
-                let promise_id = b
-                    .borrow()
-                    .as_ref()
-                    .expect(BLOCKCHAIN_INTERFACE_NOT_SET_ERR)
-                    .promise_batch_create(current_id.len() as _, current_id.as_ptr() as _);
-                b.borrow()
-                    .as_ref()
-                    .expect(BLOCKCHAIN_INTERFACE_NOT_SET_ERR)
-                    .promise_batch_action_deploy_contract(promise_id, u64::MAX as _, 0);
-                let attached_gas = env::prepaid_gas() - env::used_gas() - GAS_FOR_MIGRATE_CALL;
-                b.borrow()
-                    .as_ref()
-                    .expect(BLOCKCHAIN_INTERFACE_NOT_SET_ERR)
-                    .promise_batch_action_function_call(
-                        promise_id,
-                        method_name.len() as _,
-                        method_name.as_ptr() as _,
-                        0 as _,
-                        0 as _,
-                        0 as _,
-                        attached_gas,
-                    );
-            });
-        }
+        let mut contract: Contract = env::state_read().expect("ERR_CONTRACT_IS_NOT_INITIALIZED");
+        let code = env::input().expect("ERR_NO_INPUT");
+        contract.internal_stage_upgrade(&code);
+        env::state_write(&contract);
     }
 }
@@ -7,62 +7,167 @@ use crate::util::*;
 /// Raw type for timestamp in nanoseconds
 pub type Timestamp = u64;
 
+/// Basis points denominator (100%).
+pub const BASIS_POINTS: u128 = 10_000;
+
+/// Describes how the tokens are released between the cliff and the end of the vesting.
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "test", derive(Debug))]
+pub enum VestingSchedule {
+    /// tokens vest linearly (per nanosecond) from the start until the end.
+    Linear,
+    /// tokens vest in equal tranches, one tranche every `period` nanoseconds
+    /// (eg every month or quarter) since the start. The last tranche is released at the end.
+    Periodic { period: Timestamp },
+}
+
 /// Contains information about vesting schedule.
-#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, PartialEq)]
-#[cfg_attr(feature = "test", derive(Clone, Debug))]
+/// An account can have multiple vesting records (grants).
+#[derive(BorshDeserialize, BorshSerialize, Deserialize, Serialize, PartialEq, Clone)]
+#[cfg_attr(feature = "test", derive(Debug))]
 pub struct VestingRecord {
+    /// grant id, unique in the contract. Legacy grants not yet converted have id 0.
+    pub id: u64,
     /// amount locked in  the vesting schedule.
     /// before transferring, vesting is checked and
-    /// if we're before cliff_timestamp, locked_amount = amount
+    /// if we're before cliff_timestamp, locked_amount = amount - immediate_unlock
     /// else if we're past the end_timestamp, vesting is removed
-    /// else we're past the cliff and before end_timestamp, a linear locked_amount is computed
+    /// else we're past the cliff and before end_timestamp, the locked_amount is computed
+    /// according to the `schedule`.
     pub amount: u128,
-    /// The timestamp in nanosecond when vesting starts
-    /// The remaining tokens will vest linearly until they are fully vested.
+    /// part of the `amount` which is not locked at all (released when the grant is created).
+    pub immediate_unlock: u128,
+    /// The timestamp in nanosecond when tokens start to vest. Nothing is released before
+    /// the cliff, but at the cliff all tokens vested since the start are released
+    /// as a lump sum.
+    pub start_timestamp: Timestamp,
+    /// The timestamp in nanosecond when the first tokens are released.
+    /// The remaining tokens will vest according to the schedule until they are fully vested.
     /// Example: 1 year of employment
     pub cliff_timestamp: Timestamp,
     /// The timestamp in nanosecond when the vesting ends.
     pub end_timestamp: Timestamp,
+    pub schedule: VestingSchedule,
+    /// if the owner can cancel the grant and reclaim the not vested tokens.
+    pub revocable: bool,
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct VestingRecordJSON {
+    pub id: u64,
     pub amount: U128String,
+    pub immediate_unlock: U128String,
+    pub start_timestamp: U64String,
+    pub cliff_timestamp: U64String,
+    pub end_timestamp: U64String,
+    /// tranche period in nanoseconds, None for the linear schedule.
+    pub period: Option<U64String>,
+    pub revocable: bool,
+}
+
+/// Schedule of a new vesting grant (see `mint_vested`).
+#[derive(Deserialize, Serialize)]
+pub struct VestingParams {
+    /// when the first tokens are released.
     pub cliff_timestamp: U64String,
+    /// when the grant is fully vested.
     pub end_timestamp: U64String,
+    /// when tokens start to vest, defaults to `cliff_timestamp`. Tokens vested between
+    /// the start and the cliff are released at the cliff.
+    pub start_timestamp: Option<U64String>,
+    /// if set, tokens are released in tranches every `period` nanoseconds instead of linearly.
+    pub period: Option<U64String>,
+    /// part of the amount (in basis points) which is not locked.
+    pub immediate_unlock_bps: Option<u16>,
+    /// if the grant can be cancelled by a vesting manager, defaults to true.
+    pub revocable: Option<bool>,
 }
 
 impl VestingRecord {
-    pub fn new(amount: u128, cliff_timestamp: Timestamp, end_timestamp: Timestamp) -> Self {
+    /// Creates a new, revocable vesting record.
+    /// `immediate_unlock_bps` is the part of the amount (in basis points) which is not locked.
+    pub fn new(
+        id: u64,
+        amount: u128,
+        start_timestamp: Timestamp,
+        cliff_timestamp: Timestamp,
+        end_timestamp: Timestamp,
+        schedule: VestingSchedule,
+        immediate_unlock_bps: u16,
+    ) -> Self {
         assert!(amount > 0, "vesting amount must be > 0");
+        assert!(
+            start_timestamp <= cliff_timestamp,
+            "Start timestamp can't be later than the cliff timestamp"
+        );
         assert!(
             cliff_timestamp <= end_timestamp,
             "Cliff timestamp can't be later than vesting end timestamp"
         );
+        assert!(
+            immediate_unlock_bps as u128 <= BASIS_POINTS,
+            "immediate unlock can't be bigger than 100%"
+        );
+        if let VestingSchedule::Periodic { period } = schedule {
+            assert!(period > 0, "vesting period must be > 0");
+        }
         Self {
+            id,
             amount,
+            immediate_unlock: fraction_of(amount, immediate_unlock_bps as u128, BASIS_POINTS),
+            start_timestamp,
             cliff_timestamp,
             end_timestamp,
+            schedule,
+            revocable: true,
         }
     }
 
     /// Get the amount of tokens that are locked in this account due to vesting or release schedule.
     pub fn compute_amount_locked(&self) -> u128 {
         let block_timestamp = env::block_timestamp();
+        let amount = self.amount - self.immediate_unlock;
 
         return if block_timestamp < self.cliff_timestamp {
             // Before the cliff, all is locked
-            self.amount
+            amount
         } else if block_timestamp >= self.end_timestamp {
             // After end_timestamp none is locked
             0
         } else {
-            // compute time-left cannot overflow since block_timestamp < end_timestamp
-            let time_left = self.end_timestamp - block_timestamp;
-            // The total time is positive. Checked at the contract initialization.
-            let total_time = self.end_timestamp - self.cliff_timestamp;
-            // locked amount is linearly reduced until time_left = 0 (end_timestamp)
-            fraction_of(self.amount, time_left as u128, total_time as u128)
+            // The total time is positive: start <= cliff <= block_timestamp < end_timestamp
+            let total_time = self.end_timestamp - self.start_timestamp;
+            match self.schedule {
+                VestingSchedule::Linear => {
+                    // compute time-left cannot overflow since block_timestamp < end_timestamp
+                    let time_left = self.end_timestamp - block_timestamp;
+                    // locked amount is linearly reduced until time_left = 0 (end_timestamp)
+                    fraction_of(amount, time_left as u128, total_time as u128)
+                }
+                VestingSchedule::Periodic { period } => {
+                    // the last tranche can be shorter than the period.
+                    let tranches = (total_time - 1) / period + 1;
+                    let released = (block_timestamp - self.start_timestamp) / period;
+                    // released < tranches since block_timestamp < end_timestamp
+                    fraction_of(amount, (tranches - released) as u128, tranches as u128)
+                }
+            }
         };
     }
+
+    pub fn to_json(&self) -> VestingRecordJSON {
+        VestingRecordJSON {
+            id: self.id,
+            amount: self.amount.into(),
+            immediate_unlock: self.immediate_unlock.into(),
+            start_timestamp: self.start_timestamp.into(),
+            cliff_timestamp: self.cliff_timestamp.into(),
+            end_timestamp: self.end_timestamp.into(),
+            period: match self.schedule {
+                VestingSchedule::Linear => None,
+                VestingSchedule::Periodic { period } => Some(period.into()),
+            },
+            revocable: self.revocable,
+        }
+    }
 }
@@ -3,6 +3,8 @@ NETWORK=testnet
 OWNER=cheddar.$NETWORK
 MASTER_ACC=$OWNER
 CONTRACT_ACC=token.$MASTER_ACC
+# 100M CHEDDAR
+MAX_SUPPLY=100000000000000000000000000000000
 
 export NODE_ENV=$NETWORK
 
@@ -11,7 +13,7 @@ export NODE_ENV=$NETWORK
 #read input
 #near delete $CONTRACT_ACC $MASTER_ACC
 #near create-account $CONTRACT_ACC --masterAccount $MASTER_ACC
-#near deploy $CONTRACT_ACC ./res/cheddar_coin.wasm new "{\"owner_id\":\"$OWNER\"}" --accountId $MASTER_ACC
+#near deploy $CONTRACT_ACC ./res/cheddar_coin.wasm new "{\"owner_id\":\"$OWNER\", \"max_supply\":\"$MAX_SUPPLY\"}" --accountId $MASTER_ACC
 
 ##redeploy only
 near deploy $CONTRACT_ACC ./res/cheddar_coin.wasm  --accountId $MASTER_ACC
@@ -0,0 +1,17 @@
+use near_sdk::{Balance, Gas};
+
+/// Amount of gas for fungible token transfers.
+pub const TGAS: Gas = 1_000_000_000_000;
+pub const GAS_FOR_FT_TRANSFER: Gas = 10 * TGAS;
+pub const GAS_FOR_WITHDRAW_CALLBACK: Gas = 20 * TGAS;
+
+pub const ONE_YOCTO: Balance = 1;
+
+/// one second in nanoseconds
+pub const SECOND: u64 = 1_000_000_000;
+pub const DAY: u64 = 24 * 3600;
+/// minimum lock duration in seconds
+pub const MIN_LOCK_DURATION: u64 = 7 * DAY;
+
+const MILLI_NEAR: Balance = 1000_000000_000000_000000; // 1e21
+pub const NEAR_BALANCE: Balance = MILLI_NEAR * 50; // 0.05 NEAR
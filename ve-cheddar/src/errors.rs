@@ -0,0 +1,20 @@
+pub const ERR01_NOT_CHEDDAR: &str = "E01: only Cheddar token transfers are accepted";
+pub const ERR02_ZERO_AMOUNT: &str = "E02: lock amount must be positive";
+pub const ERR03_STORAGE_DEP: &str = "E03: min storage deposit is 0.05 NEAR";
+pub const ERR04_INVALID_MSG: &str =
+    "E04: invalid msg, expected empty msg or {\"duration\": <seconds>}";
+
+// Account registration
+
+pub const ERR10_NO_ACCOUNT: &str = "E10: account not found. Register the account.";
+pub const ERR11_NOT_EMPTY: &str = "E11: can't unregister the account with locked tokens";
+
+// Lock errors
+
+pub const ERR20_DURATION_TOO_SHORT: &str = "E20: lock duration is shorter than the min duration";
+pub const ERR21_DURATION_TOO_LONG: &str = "E21: lock duration is longer than the max duration";
+pub const ERR22_NO_LOCK: &str = "E22: no active lock, provide the lock duration";
+pub const ERR23_LOCK_EXPIRED: &str = "E23: lock expired, withdraw or extend the lock first";
+pub const ERR24_EXTEND_SHORTER: &str = "E24: new unlock time must be later than the current one";
+pub const ERR25_NOT_UNLOCKED: &str = "E25: tokens are still locked";
+pub const ERR26_NOTHING_TO_WITHDRAW: &str = "E26: nothing to withdraw";
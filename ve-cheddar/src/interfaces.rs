@@ -0,0 +1,40 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{ext_contract, AccountId};
+
+#[ext_contract(ext_self)]
+pub trait ExtSelf {
+    fn withdraw_callback(&mut self, user: AccountId, amount: U128, unlock_at: u64);
+}
+
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+/// `ft_transfer_call` message. An empty message adds tokens to the current lock.
+#[derive(Deserialize, Serialize)]
+pub struct LockMsg {
+    /// lock duration in seconds from now. Creates a new lock or extends the current one.
+    pub duration: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ContractParams {
+    pub owner_id: AccountId,
+    pub cheddar: AccountId,
+    /// maximum lock duration in seconds.
+    pub max_lock_duration: u64,
+    pub total_locked: U128,
+    /// Number of accounts currently registered.
+    pub accounts_registered: u64,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct LockView {
+    pub amount: U128,
+    /// unix timestamp (seconds) when the tokens are unlocked.
+    pub unlock_at: u64,
+    /// current voting power, decays linearly to 0 at `unlock_at`.
+    pub voting_power: U128,
+}
@@ -0,0 +1,332 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::{ValidAccountId, U128};
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, AccountId, Balance, PanicOnDefault, Promise,
+    PromiseResult,
+};
+
+pub mod constants;
+pub mod errors;
+pub mod interfaces;
+pub mod lock;
+pub mod util;
+
+use crate::interfaces::*;
+use crate::{constants::*, errors::*, lock::*};
+
+near_sdk::setup_alloc!();
+
+/// Vote escrowed Cheddar: users lock Cheddar for a chosen duration (up to
+/// `max_lock_duration`) to receive governance voting power. Voting power is proportional
+/// to the locked amount and the remaining lock time, so it decays linearly to zero
+/// at the unlock time. Tokens can be withdrawn only after the unlock time.
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    pub owner_id: AccountId,
+    /// Cheddar token contract
+    pub cheddar: AccountId,
+    /// user locks
+    pub locks: LookupMap<AccountId, Lock>,
+    /// maximum lock duration in seconds. Locking for the max duration gives voting power
+    /// equal to the locked amount.
+    pub max_lock_duration: u64,
+    /// total amount of currently locked Cheddar.
+    pub total_locked: Balance,
+    /// total number of accounts currently registered.
+    pub accounts_registered: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Initializes the contract.
+    /// Parameters:
+    /// * `cheddar`: the Cheddar token contract.
+    /// * `max_lock_duration`: maximum lock duration in seconds (eg 4 years).
+    #[init]
+    pub fn new(owner_id: ValidAccountId, cheddar: ValidAccountId, max_lock_duration: u64) -> Self {
+        assert!(
+            max_lock_duration >= MIN_LOCK_DURATION,
+            "max lock duration must be at least the min lock duration"
+        );
+        Self {
+            owner_id: owner_id.into(),
+            cheddar: cheddar.into(),
+            locks: LookupMap::new(b"l".to_vec()),
+            max_lock_duration,
+            total_locked: 0,
+            accounts_registered: 0,
+        }
+    }
+
+    // ************ //
+    // view methods //
+
+    pub fn get_contract_params(&self) -> ContractParams {
+        ContractParams {
+            owner_id: self.owner_id.clone(),
+            cheddar: self.cheddar.clone(),
+            max_lock_duration: self.max_lock_duration,
+            total_locked: self.total_locked.into(),
+            accounts_registered: self.accounts_registered,
+        }
+    }
+
+    /// Returns the account lock, or None if the account is not registered.
+    pub fn get_lock(&self, account_id: ValidAccountId) -> Option<LockView> {
+        self.locks.get(account_id.as_ref()).map(|l| LockView {
+            amount: l.amount.into(),
+            unlock_at: l.unlock_at,
+            voting_power: l.voting_power(now(), self.max_lock_duration).into(),
+        })
+    }
+
+    /// Returns the current voting power of the account.
+    pub fn voting_power(&self, account_id: ValidAccountId) -> U128 {
+        match self.locks.get(account_id.as_ref()) {
+            Some(l) => l.voting_power(now(), self.max_lock_duration).into(),
+            None => 0.into(),
+        }
+    }
+
+    // ******************* //
+    // transaction methods //
+
+    /// Extends the caller lock: sets the unlock time to now + `duration` (in seconds).
+    /// The new unlock time must be later than the current one.
+    /// Requires 1 yNEAR payment for wallet 2FA.
+    #[payable]
+    pub fn extend_lock(&mut self, duration: u64) {
+        assert_one_yocto();
+        let a = env::predecessor_account_id();
+        let mut l = self.internal_get_lock(&a);
+        assert!(l.amount > 0, "{}", ERR22_NO_LOCK);
+        let unlock_at = self.unlock_at(duration);
+        assert!(unlock_at > l.unlock_at, "{}", ERR24_EXTEND_SHORTER);
+        l.unlock_at = unlock_at;
+        self.locks.insert(&a, &l);
+    }
+
+    /// Withdraws all unlocked Cheddar to the caller. It doesn't close the account.
+    /// Panics if the tokens are still locked.
+    /// Requires 1 yNEAR payment for wallet 2FA.
+    #[payable]
+    pub fn withdraw(&mut self) -> Promise {
+        assert_one_yocto();
+        let a = env::predecessor_account_id();
+        let mut l = self.internal_get_lock(&a);
+        assert!(l.amount > 0, "{}", ERR26_NOTHING_TO_WITHDRAW);
+        assert!(now() >= l.unlock_at, "{}", ERR25_NOT_UNLOCKED);
+        let amount = l.amount;
+        let unlock_at = l.unlock_at;
+        // zero the lock to block double withdraw, it's recovered in the callback on failure.
+        l.amount = 0;
+        self.locks.insert(&a, &l);
+        self.total_locked -= amount;
+        ext_ft::ft_transfer(
+            a.clone(),
+            amount.into(),
+            Some("ve-cheddar unlock".to_string()),
+            &self.cheddar,
+            ONE_YOCTO,
+            GAS_FOR_FT_TRANSFER,
+        )
+        .then(ext_self::withdraw_callback(
+            a,
+            amount.into(),
+            unlock_at,
+            &env::current_account_id(),
+            0,
+            GAS_FOR_WITHDRAW_CALLBACK,
+        ))
+    }
+
+    #[private]
+    pub fn withdraw_callback(&mut self, user: AccountId, amount: U128, unlock_at: u64) {
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                log!("Cheddar withdrew {}", amount.0);
+            }
+            PromiseResult::Failed => {
+                log!(
+                    "Cheddar transfer failed {}. recovering account state",
+                    amount.0
+                );
+                let mut l = match self.locks.get(&user) {
+                    Some(l) => l,
+                    // the account was closed before by another TX
+                    None => {
+                        self.accounts_registered += 1;
+                        Lock {
+                            amount: 0,
+                            unlock_at,
+                        }
+                    }
+                };
+                l.amount += amount.0;
+                self.locks.insert(&user, &l);
+                self.total_locked += amount.0;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+#[allow(unused_imports)]
+mod tests {
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::MockedBlockchain;
+    use near_sdk::{testing_env, Balance};
+    use std::convert::TryInto;
+
+    use super::*;
+
+    fn acc_cheddar() -> ValidAccountId {
+        "cheddar".try_into().unwrap()
+    }
+
+    fn acc_user1() -> ValidAccountId {
+        "user1".try_into().unwrap()
+    }
+
+    const MAX_DURATION: u64 = 400 * DAY;
+
+    /// `day` is the block timestamp in days.
+    fn setup_contract(predecessor: ValidAccountId, day: u64) -> (VMContextBuilder, Contract) {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.build());
+        let mut contract = Contract::new(accounts(0), acc_cheddar(), MAX_DURATION);
+        testing_env!(context
+            .predecessor_account_id(acc_user1())
+            .attached_deposit(NEAR_BALANCE)
+            .build());
+        contract.storage_deposit(None, None);
+        testing_env!(context
+            .predecessor_account_id(predecessor)
+            .attached_deposit(1)
+            .block_timestamp(day * DAY * SECOND)
+            .build());
+        (context, contract)
+    }
+
+    fn lock(ctx: &mut VMContextBuilder, ctr: &mut Contract, amount: Balance, msg: &str) {
+        testing_env!(ctx.predecessor_account_id(acc_cheddar()).build());
+        ctr.ft_on_transfer(acc_user1(), amount.into(), msg.to_string());
+        testing_env!(ctx.predecessor_account_id(acc_user1()).build());
+    }
+
+    #[test]
+    fn test_create_lock() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 0);
+        assert!(ctr.get_lock(accounts(2)).is_none());
+        let l = ctr.get_lock(acc_user1()).unwrap();
+        assert_eq!(l.amount.0, 0);
+        assert_eq!(l.voting_power.0, 0);
+        assert_eq!(ctr.get_contract_params().accounts_registered, 1);
+
+        lock(&mut ctx, &mut ctr, 100, r#"{"duration": 8640000}"#);
+        let l = ctr.get_lock(acc_user1()).unwrap();
+        assert_eq!(l.amount.0, 100);
+        assert_eq!(l.unlock_at, 100 * DAY);
+        assert_eq!(l.voting_power.0, 25);
+        assert_eq!(ctr.get_contract_params().total_locked.0, 100);
+    }
+
+    #[test]
+    fn test_lock_and_decay() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 10);
+        lock(&mut ctx, &mut ctr, 400, r#"{"duration": 34560000}"#);
+        let l = ctr.get_lock(acc_user1()).unwrap();
+        assert_eq!(l.unlock_at, 410 * DAY);
+        assert_eq!(l.voting_power.0, 400);
+        assert_eq!(ctr.get_contract_params().total_locked.0, 400);
+
+        testing_env!(ctx.block_timestamp(110 * DAY * SECOND).build());
+        assert_eq!(ctr.voting_power(acc_user1()).0, 300);
+        // add to the current lock
+        lock(&mut ctx, &mut ctr, 400, "");
+        assert_eq!(ctr.voting_power(acc_user1()).0, 600);
+
+        testing_env!(ctx.block_timestamp(410 * DAY * SECOND).build());
+        assert_eq!(ctr.voting_power(acc_user1()).0, 0);
+        ctr.withdraw();
+        assert_eq!(ctr.get_lock(acc_user1()).unwrap().amount.0, 0);
+        assert_eq!(ctr.get_contract_params().total_locked.0, 0);
+    }
+
+    #[test]
+    fn test_extend_lock() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 0);
+        lock(&mut ctx, &mut ctr, 100, r#"{"duration": 864000}"#);
+        assert_eq!(ctr.voting_power(acc_user1()).0, 2);
+        ctr.extend_lock(MAX_DURATION);
+        assert_eq!(ctr.voting_power(acc_user1()).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "E24: new unlock time must be later than the current one")]
+    fn test_extend_lock_shorter() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 0);
+        lock(&mut ctx, &mut ctr, 100, r#"{"duration": 8640000}"#);
+        ctr.extend_lock(10 * DAY);
+    }
+
+    #[test]
+    #[should_panic(expected = "E23: lock expired, withdraw or extend the lock first")]
+    fn test_add_to_expired_lock() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 0);
+        lock(&mut ctx, &mut ctr, 100, r#"{"duration": 864000}"#);
+        testing_env!(ctx.block_timestamp(10 * DAY * SECOND).build());
+        lock(&mut ctx, &mut ctr, 100, "");
+    }
+
+    #[test]
+    fn test_withdraw_failed_transfer() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 0);
+        lock(&mut ctx, &mut ctr, 100, r#"{"duration": 864000}"#);
+        testing_env!(ctx.block_timestamp(10 * DAY * SECOND).build());
+        ctr.withdraw();
+        assert_eq!(ctr.get_lock(acc_user1()).unwrap().amount.0, 0);
+        assert_eq!(ctr.get_contract_params().total_locked.0, 0);
+
+        // the Cheddar transfer failed: the lock is recovered
+        testing_env!(
+            ctx.predecessor_account_id(accounts(0)).build(),
+            near_sdk::VMConfig::default(),
+            near_sdk::RuntimeFeesConfig::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        ctr.withdraw_callback(acc_user1().into(), 100.into(), 10 * DAY);
+        let l = ctr.get_lock(acc_user1()).unwrap();
+        assert_eq!(l.amount.0, 100);
+        assert_eq!(l.unlock_at, 10 * DAY);
+        assert_eq!(ctr.get_contract_params().total_locked.0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "E25: tokens are still locked")]
+    fn test_withdraw_locked() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 0);
+        lock(&mut ctx, &mut ctr, 100, r#"{"duration": 864000}"#);
+        ctr.withdraw();
+    }
+
+    #[test]
+    #[should_panic(expected = "E21: lock duration is longer than the max duration")]
+    fn test_lock_too_long() {
+        let (mut ctx, mut ctr) = setup_contract(acc_user1(), 0);
+        lock(&mut ctx, &mut ctr, 100, r#"{"duration": 34560001}"#);
+    }
+
+    #[test]
+    #[should_panic(expected = "E01: only Cheddar token transfers are accepted")]
+    fn test_wrong_token() {
+        let (_, mut ctr) = setup_contract(accounts(2), 0);
+        ctr.ft_on_transfer(acc_user1(), 100.into(), "".to_string());
+    }
+}
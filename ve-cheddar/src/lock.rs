@@ -0,0 +1,208 @@
+//! Lock is information per user about their locked Cheddar.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{ValidAccountId, U128};
+use near_sdk::{env, log, AccountId, Balance, PromiseOrValue};
+
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+
+use crate::util::*;
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(feature = "test", derive(Default, Clone))]
+pub struct Lock {
+    /// amount of locked Cheddar
+    pub amount: Balance,
+    /// unix timestamp (seconds) when the tokens are unlocked.
+    pub unlock_at: u64,
+}
+
+impl Lock {
+    /**
+    Voting power of the lock at `now` (unix timestamp in seconds):
+    `amount * time_left / max_lock_duration`.
+    It decays linearly and is 0 once the tokens are unlocked.
+     */
+    pub fn voting_power(&self, now: u64, max_lock_duration: u64) -> u128 {
+        if now >= self.unlock_at {
+            return 0;
+        }
+        fraction_of(
+            self.amount,
+            (self.unlock_at - now) as u128,
+            max_lock_duration as u128,
+        )
+    }
+}
+
+impl Contract {
+    #[inline]
+    pub(crate) fn internal_get_lock(&self, account_id: &AccountId) -> Lock {
+        self.locks.get(account_id).expect(ERR10_NO_ACCOUNT)
+    }
+
+    /// Validates the lock duration and returns the unlock timestamp.
+    pub(crate) fn unlock_at(&self, duration: u64) -> u64 {
+        assert!(
+            duration >= MIN_LOCK_DURATION,
+            "{}",
+            ERR20_DURATION_TOO_SHORT
+        );
+        assert!(
+            duration <= self.max_lock_duration,
+            "{}",
+            ERR21_DURATION_TOO_LONG
+        );
+        now() + duration
+    }
+}
+
+/// current unix timestamp in seconds
+#[inline]
+pub(crate) fn now() -> u64 {
+    env::block_timestamp() / SECOND
+}
+
+// Cheddar deposits are done through NEP-141 ft_transfer_call to this contract.
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /**
+    FungibleTokenReceiver implementation
+    Callback on receiving tokens by this contract.
+    Locks the received Cheddar. `msg` must be either:
+    * empty: adds tokens to the current, not expired lock.
+    * `{"duration": <seconds>}`: adds tokens and sets the unlock time to now + duration. The
+      unlock time can't be earlier than the current one.
+    Returns zero.
+    Panics when the account is not registered or when receiving a wrong token. */
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert!(
+            env::predecessor_account_id() == self.cheddar,
+            "{}",
+            ERR01_NOT_CHEDDAR
+        );
+        assert!(amount.0 > 0, "{}", ERR02_ZERO_AMOUNT);
+        let sender_id: &AccountId = sender_id.as_ref();
+        let mut l = self.internal_get_lock(sender_id);
+        let duration = if msg.is_empty() {
+            None
+        } else {
+            serde_json::from_str::<LockMsg>(&msg)
+                .expect(ERR04_INVALID_MSG)
+                .duration
+        };
+        match duration {
+            Some(d) => {
+                let unlock_at = self.unlock_at(d);
+                assert!(unlock_at >= l.unlock_at, "{}", ERR24_EXTEND_SHORTER);
+                l.unlock_at = unlock_at;
+            }
+            None => {
+                assert!(l.amount > 0, "{}", ERR22_NO_LOCK);
+                assert!(now() < l.unlock_at, "{}", ERR23_LOCK_EXPIRED);
+            }
+        }
+        log!("Locked {} Cheddar until {}", amount.0, l.unlock_at);
+        l.amount += amount.0;
+        self.locks.insert(sender_id, &l);
+        self.total_locked += amount.0;
+
+        return PromiseOrValue::Value(U128(0));
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    /// Registers a new account
+    #[allow(unused_variables)]
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<ValidAccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount: Balance = env::attached_deposit();
+        let account_id = account_id
+            .map(|a| a.into())
+            .unwrap_or_else(|| env::predecessor_account_id());
+        if self.locks.contains_key(&account_id) {
+            log!("The account is already registered, refunding the deposit");
+            if amount > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(amount);
+            }
+        } else {
+            assert!(amount >= NEAR_BALANCE, "{}", ERR03_STORAGE_DEP);
+            self.locks.insert(
+                &account_id,
+                &Lock {
+                    amount: 0,
+                    unlock_at: 0,
+                },
+            );
+            self.accounts_registered += 1;
+
+            let refund = amount - NEAR_BALANCE;
+            if refund > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(refund);
+            }
+        }
+        storage_balance()
+    }
+
+    /// Storage withdraw is not possible, use `storage_unregister` to close the account.
+    #[allow(unused_variables)]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        panic!("Storage withdraw not possible, close the account instead");
+    }
+
+    /// Closes the account and returns the storage deposit. The account must not have
+    /// any locked tokens (withdraw them first).
+    #[allow(unused_variables)]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let a = env::predecessor_account_id();
+        match self.locks.get(&a) {
+            Some(l) => {
+                assert!(l.amount == 0, "{}", ERR11_NOT_EMPTY);
+                self.locks.remove(&a);
+                self.accounts_registered -= 1;
+                Promise::new(a).transfer(NEAR_BALANCE + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mix and min balance is always NEAR_BALANCE.
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: NEAR_BALANCE.into(),
+            max: Some(NEAR_BALANCE.into()),
+        }
+    }
+
+    /// If the account is registered the total balance is always NEAR_BALANCE.
+    /// Otherwise None.
+    fn storage_balance_of(&self, account_id: ValidAccountId) -> Option<StorageBalance> {
+        if self.locks.contains_key(account_id.as_ref()) {
+            return Some(storage_balance());
+        }
+        None
+    }
+}
+
+fn storage_balance() -> StorageBalance {
+    StorageBalance {
+        total: NEAR_BALANCE.into(),
+        available: U128::from(0),
+    }
+}